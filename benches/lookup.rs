@@ -0,0 +1,101 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use maxminddb::{geoip2, ReadAt, Reader, ReaderOptions};
+
+// Place a copy of the GeoIP2 City database here to benchmark against real
+// data, the test database is used otherwise.
+const DB_FILE: &str = "GeoIP2-City.mmdb";
+const TEST_DB_FILE: &str = "test-data/test-data/GeoIP2-City-Test.mmdb";
+
+fn db_file() -> &'static str {
+    if Path::new(DB_FILE).exists() {
+        DB_FILE
+    } else {
+        TEST_DB_FILE
+    }
+}
+
+// The same pseudo-random addresses on every run
+fn generate_ipv4(count: usize) -> Vec<IpAddr> {
+    let mut state: u32 = 0x2545_f491;
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            IpAddr::V4(Ipv4Addr::from(state))
+        })
+        .collect()
+}
+
+async fn lookup_all<S: ReadAt>(reader: &mut Reader<S>, ips: &[IpAddr]) {
+    for ip in ips {
+        let _ = reader.lookup::<geoip2::City>(*ip).await;
+    }
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let ips = generate_ipv4(100);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("lookup");
+
+    let mut reader = runtime.block_on(Reader::open_readfile(db_file())).unwrap();
+    group.bench_function("tokio_file", |b| {
+        b.iter(|| runtime.block_on(lookup_all(&mut reader, &ips)))
+    });
+
+    let options = ReaderOptions::new().load_search_tree(true);
+    let mut reader = runtime.block_on(options.open(db_file())).unwrap();
+    group.bench_function("tokio_file_search_tree", |b| {
+        b.iter(|| runtime.block_on(lookup_all(&mut reader, &ips)))
+    });
+
+    let mut reader = runtime.block_on(Reader::open_pread(db_file())).unwrap();
+    group.bench_function("pread", |b| {
+        b.iter(|| runtime.block_on(lookup_all(&mut reader, &ips)))
+    });
+
+    #[cfg(feature = "mmap")]
+    {
+        let mut reader = runtime.block_on(Reader::open_mmap(db_file())).unwrap();
+        group.bench_function("mmap", |b| {
+            b.iter(|| runtime.block_on(lookup_all(&mut reader, &ips)))
+        });
+    }
+
+    let buf = std::fs::read(db_file()).unwrap();
+    let mut reader = runtime.block_on(Reader::from_bytes(buf)).unwrap();
+    group.bench_function("in_memory", |b| {
+        b.iter(|| runtime.block_on(lookup_all(&mut reader, &ips)))
+    });
+
+    let mut reader = maxminddb::blocking::Reader::open_readfile(db_file()).unwrap();
+    group.bench_function("blocking", |b| {
+        b.iter(|| {
+            for ip in &ips {
+                let _ = reader.lookup::<geoip2::City>(*ip);
+            }
+        })
+    });
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    {
+        let uring = tokio_uring::Runtime::new(&tokio_uring::builder()).unwrap();
+        let mut reader = uring.block_on(Reader::open_uring(db_file())).unwrap();
+        group.bench_function("io_uring", |b| {
+            b.iter(|| uring.block_on(lookup_all(&mut reader, &ips)))
+        });
+        // The file is closed through the ring
+        uring.block_on(async move { drop(reader) });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,196 @@
+//! A synchronous reader for code that doesn't run on an async runtime.
+//!
+//! [`Reader`] reads from any [`Read`] + [`Seek`] source, such as a
+//! [`std::fs::File`], and shares the tree walk and the decoder with the async
+//! [`crate::Reader`]. No runtime is needed, the reads simply block.
+//!
+//! ```
+//! use maxminddb::geoip2;
+//!
+//! let mut reader = maxminddb::blocking::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+//!
+//! let city: geoip2::City = reader.lookup("89.160.20.128").unwrap();
+//! print!("{:?}", city);
+//! ```
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use ipnetwork::IpNetwork;
+use serde::de::DeserializeOwned;
+
+use super::index::Index;
+use super::stats::Stats;
+use super::{
+    IntoLookupAddr, MaxMindDBError, Metadata, NetworksOptions, ReadAt, ReaderOptions, Record, Source, WithinItem,
+};
+
+/// A reader for the MaxMind DB format that blocks on I/O. See the async
+/// [`crate::Reader`] for the documentation of the methods.
+pub struct Reader<R: Read + Seek> {
+    inner: super::Reader<SyncStream<R>>,
+}
+
+impl Reader<File> {
+    pub fn open_readfile(database: &str) -> Result<Reader<File>, MaxMindDBError> {
+        Reader::from_reader(File::open(database)?)
+    }
+}
+
+impl Reader<Cursor<Vec<u8>>> {
+    /// Open a database held in memory, e.g. one built with
+    /// [`crate::writer::Writer`].
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Reader<Cursor<Vec<u8>>>, MaxMindDBError> {
+        Reader::from_reader(Cursor::new(buf))
+    }
+
+    /// Open a compressed database, decompressing it into memory. See
+    /// [`crate::compressed`] for the supported formats.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn open_compressed<P: AsRef<std::path::Path>>(database: P) -> Result<Reader<Cursor<Vec<u8>>>, MaxMindDBError> {
+        Reader::from_bytes(crate::compressed::read_file(database)?)
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    pub fn from_reader(reader: R) -> Result<Reader<R>, MaxMindDBError> {
+        Reader::from_reader_with_options(reader, &ReaderOptions::new())
+    }
+
+    pub fn from_reader_with_options(mut reader: R, options: &ReaderOptions) -> Result<Reader<R>, MaxMindDBError> {
+        let total_size = reader.seek(SeekFrom::End(0))? as usize;
+        let source = Source::from_read_at(SyncStream(reader), total_size);
+        let inner = block_on(options.open_source(source))?;
+        Ok(Reader { inner })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.inner.metadata
+    }
+
+    pub fn metadata_as<T: DeserializeOwned>(&mut self) -> Result<T, MaxMindDBError> {
+        block_on(self.inner.metadata_as())
+    }
+
+    pub fn data_section_size(&self) -> usize {
+        self.inner.data_section_size()
+    }
+
+    pub fn lookup<T: DeserializeOwned>(&mut self, address: impl IntoLookupAddr) -> Result<T, MaxMindDBError> {
+        block_on(self.inner.lookup(address))
+    }
+
+    pub fn lookup_prefix<T: DeserializeOwned>(
+        &mut self,
+        address: impl IntoLookupAddr,
+    ) -> Result<(T, usize), MaxMindDBError> {
+        block_on(self.inner.lookup_prefix(address))
+    }
+
+    /// Iterate over all networks contained in `cidr` together with their
+    /// records.
+    pub fn within<T: DeserializeOwned>(&mut self, cidr: IpNetwork) -> Result<Within<'_, T, R>, MaxMindDBError> {
+        let inner = block_on(self.inner.within(cidr))?;
+        Ok(Within { inner })
+    }
+
+    /// Iterate over all networks of the database, see
+    /// [`NetworksOptions`].
+    pub fn networks<T: DeserializeOwned>(
+        &mut self,
+        options: NetworksOptions,
+    ) -> Result<Networks<'_, T, R>, MaxMindDBError> {
+        let inner = block_on(self.inner.networks(options))?;
+        Ok(Networks { inner })
+    }
+
+    /// Finds the networks whose record matches `predicate`, decoding each
+    /// distinct record once after walking the tree.
+    pub fn find_networks<T, F>(&mut self, predicate: F) -> Result<Vec<IpNetwork>, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+        F: FnMut(&T) -> bool,
+    {
+        block_on(self.inner.find_networks(predicate))
+    }
+
+    /// Builds an index from the field at `path` of the records to their
+    /// networks.
+    pub fn index_by<K: DeserializeOwned + Ord>(&mut self, path: &str) -> Result<Index<K>, MaxMindDBError> {
+        block_on(self.inner.index_by(path))
+    }
+
+    /// Builds the index of [`Reader::index_by`] on the first call only and
+    /// returns the same one afterwards.
+    pub fn cached_index<K>(&mut self, path: &str) -> Result<Arc<Index<K>>, MaxMindDBError>
+    where
+        K: DeserializeOwned + Ord + Send + Sync + 'static,
+    {
+        block_on(self.inner.cached_index(path))
+    }
+
+    /// Reports the shape of the search tree and the data section.
+    pub fn stats(&mut self) -> Result<Stats, MaxMindDBError> {
+        block_on(self.inner.stats())
+    }
+
+    /// The left and right records of a node of the search tree.
+    pub fn node(&mut self, node: usize) -> Result<(Record, Record), MaxMindDBError> {
+        block_on(self.inner.node(node))
+    }
+
+    /// The nodes a lookup of `address` goes through.
+    pub fn path_for(&mut self, address: impl IntoLookupAddr) -> Result<Vec<usize>, MaxMindDBError> {
+        block_on(self.inner.path_for(address))
+    }
+}
+
+/// An iterator over the networks of a database, see [`Reader::within`].
+pub struct Within<'a, T: DeserializeOwned, R: Read + Seek> {
+    inner: super::Within<'a, T, SyncStream<R>>,
+}
+
+impl<T: DeserializeOwned, R: Read + Seek> Iterator for Within<'_, T, R> {
+    type Item = Result<WithinItem<T>, MaxMindDBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(self.inner.next()).transpose()
+    }
+}
+
+/// An iterator over all networks of a database, see [`Reader::networks`].
+pub struct Networks<'a, T: DeserializeOwned, R: Read + Seek> {
+    inner: super::Networks<'a, T, SyncStream<R>>,
+}
+
+impl<T: DeserializeOwned, R: Read + Seek> Iterator for Networks<'_, T, R> {
+    type Item = Result<WithinItem<Option<T>>, MaxMindDBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(self.inner.next()).transpose()
+    }
+}
+
+// Drives a future whose I/O is done by a `SyncStream`. Such futures never
+// wait, so they complete on the first poll.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let future = pin!(future);
+    match future.poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("blocking reads never pend"),
+    }
+}
+
+// Reads from a blocking stream, the returned futures are always ready.
+struct SyncStream<R>(R);
+
+impl<R: Read + Seek> ReadAt for SyncStream<R> {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        let result = self.0.seek(SeekFrom::Start(offset)).and_then(|_| self.0.read_exact(buf));
+        std::future::ready(result)
+    }
+}
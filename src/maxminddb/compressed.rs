@@ -0,0 +1,237 @@
+//! Reading compressed databases into memory.
+//!
+//! Databases compressed with gzip (`.mmdb.gz`, the `gzip` feature) or zstd
+//! (`.mmdb.zst`, the `zstd` feature), as well as MaxMind's `.tar.gz` download
+//! archives, are decompressed into memory and read with the in-memory reader.
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut reader = maxminddb::Reader::open_compressed("GeoLite2-City.mmdb.gz").await.unwrap();
+//! let city: maxminddb::geoip2::City = reader.lookup("89.160.20.128").await.unwrap();
+//! # }
+//! ```
+//!
+//! Download archives, e.g. `GeoLite2-City_20240102.tar.gz`, hold the database
+//! in a dated directory. [`open_archive`] picks a database by its edition and
+//! returns the date of the archive along with it:
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! use maxminddb::compressed::open_archive;
+//!
+//! let database = open_archive("GeoLite2-City_20240102.tar.gz", Some("GeoLite2-City")).unwrap();
+//! println!("{} of {:?}", database.edition, database.date);
+//! let reader = database.into_reader().await.unwrap();
+//! # }
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{MaxMindDBError, Reader};
+#[cfg(feature = "gzip")]
+use super::{decode_buf, find_metadata_start_in, Metadata};
+
+/// The compression of a database file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// A gzip compressed database, `.mmdb.gz`
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// A gzip compressed tar archive holding a database, `.tar.gz`
+    #[cfg(feature = "gzip")]
+    TarGz,
+    /// A zstd compressed database, `.mmdb.zst`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression from the extension of `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Compression> {
+        let name = path.as_ref().file_name()?.to_str()?;
+        #[cfg(feature = "gzip")]
+        {
+            if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+                return Some(Compression::TarGz);
+            }
+            if name.ends_with(".gz") {
+                return Some(Compression::Gzip);
+            }
+        }
+        #[cfg(feature = "zstd")]
+        {
+            if name.ends_with(".zst") {
+                return Some(Compression::Zstd);
+            }
+        }
+        None
+    }
+}
+
+/// Decompresses a database. For archives the first `.mmdb` file is
+/// returned.
+pub fn decompress<R: Read>(input: R, compression: Compression) -> Result<Vec<u8>, MaxMindDBError> {
+    let mut buf = Vec::new();
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            flate2::read::GzDecoder::new(input).read_to_end(&mut buf)?;
+        }
+        #[cfg(feature = "gzip")]
+        Compression::TarGz => buf = read_archive(input, None)?.bytes,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            zstd::stream::read::Decoder::new(input)?.read_to_end(&mut buf)?;
+        }
+    }
+    Ok(buf)
+}
+
+/// Reads and decompresses the database at `path`, the compression is
+/// detected from the extension.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, MaxMindDBError> {
+    let path = path.as_ref();
+    let compression = Compression::from_path(path).ok_or_else(|| {
+        MaxMindDBError::InvalidDatabaseError(format!("unknown compression: {}", path.display()))
+    })?;
+    decompress(File::open(path)?, compression)
+}
+
+/// The date of a download archive, from the `_YYYYMMDD` suffix of its name.
+/// Later archives compare greater.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArchiveDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl FromStr for ArchiveDate {
+    type Err = MaxMindDBError;
+
+    fn from_str(s: &str) -> Result<ArchiveDate, MaxMindDBError> {
+        let invalid =
+            || MaxMindDBError::InvalidDatabaseError(format!("invalid archive date: {}", s));
+        if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let date = ArchiveDate {
+            year: s[..4].parse().map_err(|_| invalid())?,
+            month: s[4..6].parse().map_err(|_| invalid())?,
+            day: s[6..].parse().map_err(|_| invalid())?,
+        };
+        if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+            return Err(invalid());
+        }
+        Ok(date)
+    }
+}
+
+/// Formats the date as in archive names, `YYYYMMDD`.
+impl fmt::Display for ArchiveDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+}
+
+// The date of names such as `GeoLite2-City_20240102`
+#[cfg(feature = "gzip")]
+fn date_suffix(name: &str) -> Option<ArchiveDate> {
+    let name = name
+        .strip_suffix(".tar.gz")
+        .or_else(|| name.strip_suffix(".tgz"))
+        .unwrap_or(name);
+    name.rsplit_once('_')?.1.parse().ok()
+}
+
+/// A database read from a download archive.
+#[derive(Debug)]
+pub struct ArchiveDatabase {
+    /// The `database_type` of the database's metadata, e.g.
+    /// `GeoLite2-City`
+    pub edition: String,
+    /// The date of the directory holding the database, if it has one
+    pub date: Option<ArchiveDate>,
+    pub bytes: Vec<u8>,
+}
+
+impl ArchiveDatabase {
+    pub async fn into_reader(self) -> Result<Reader<Cursor<Vec<u8>>>, MaxMindDBError> {
+        Reader::from_bytes(self.bytes).await
+    }
+}
+
+/// Reads the database of the `database_type` edition from a `.tar.gz`
+/// archive, or the first database of the archive when `None`. Editions are
+/// told apart by the `database_type` of their metadata, not by file name.
+/// `.mmdb` files whose metadata can't be decoded are skipped.
+#[cfg(feature = "gzip")]
+pub fn read_archive<R: Read>(
+    input: R,
+    database_type: Option<&str>,
+) -> Result<ArchiveDatabase, MaxMindDBError> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(input));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.extension().is_none_or(|ext| ext != "mmdb") {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        let edition = match decode_metadata(&bytes) {
+            Ok(metadata) => metadata.database_type,
+            Err(_) => continue,
+        };
+        if database_type.is_some_and(|database_type| database_type != edition) {
+            continue;
+        }
+        let date = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .and_then(|dir| dir.to_str())
+            .and_then(date_suffix);
+        return Ok(ArchiveDatabase {
+            edition,
+            date,
+            bytes,
+        });
+    }
+    Err(MaxMindDBError::InvalidDatabaseError(match database_type {
+        Some(database_type) => format!("no {} database in archive", database_type),
+        None => "no .mmdb file in archive".to_owned(),
+    }))
+}
+
+// Decodes the metadata at the end of a database held in memory
+#[cfg(feature = "gzip")]
+fn decode_metadata(buf: &[u8]) -> Result<Metadata, MaxMindDBError> {
+    let start = find_metadata_start_in(buf, 0)?;
+    decode_buf(&buf[start..], 0)?
+        .ok_or_else(|| MaxMindDBError::DecodingError("Couldn't decode Metadata".to_owned()))
+}
+
+/// Reads a database from the `.tar.gz` archive at `path`, see
+/// [`read_archive`]. When the database isn't in a dated directory, the date
+/// is taken from the name of the archive.
+#[cfg(feature = "gzip")]
+pub fn open_archive<P: AsRef<Path>>(
+    path: P,
+    database_type: Option<&str>,
+) -> Result<ArchiveDatabase, MaxMindDBError> {
+    let path = path.as_ref();
+    let mut database = read_archive(File::open(path)?, database_type)?;
+    if database.date.is_none() {
+        database.date = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(date_suffix);
+    }
+    Ok(database)
+}
@@ -1,12 +1,14 @@
 use log::debug;
 use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
 use serde::forward_to_deserialize_any;
-use serde::serde_if_integer128;
+use serde::Deserialize;
 use std::convert::TryInto;
 
 use super::MaxMindDBError;
 use super::MaxMindDBError::DecodingError;
 
+// I haven't moved all patterns of this form to a generic function as
+// the FromPrimitive trait is unstable
 fn to_usize(base: u8, bytes: &[u8]) -> usize {
     bytes
         .iter()
@@ -29,56 +31,167 @@ enum Value<'a, 'de> {
     Array(ArrayAccess<'a, 'de>),
 }
 
+/// How a record is encoded, see [`Decoder::record_layout`].
+#[derive(Debug, Default)]
+pub struct RecordLayout {
+    /// The size of the record, not counting the data its pointers point to
+    pub size: usize,
+    /// The size of each entry of a map record, key included
+    pub keys: Vec<(String, usize)>,
+    /// The data section offsets the record points to
+    pub pointers: Vec<usize>,
+}
+
 #[derive(Debug)]
 pub struct Decoder<'de> {
-    buf: &'de [u8],
+    // Parts of the data, with the offset each starts at
+    windows: Vec<(usize, &'de [u8])>,
     current_ptr: usize,
+    // The offset and size of the first read no window held
+    missing: Option<(usize, usize)>,
 }
 
 impl<'de> Decoder<'de> {
     pub fn new(buf: &'de [u8], start_ptr: usize) -> Decoder<'de> {
+        Decoder::with_windows(vec![(0, buf)], start_ptr)
+    }
+
+    /// A decoder over parts of the data section, each given with the offset
+    /// it starts at.
+    pub fn with_windows(windows: Vec<(usize, &'de [u8])>, start_ptr: usize) -> Decoder<'de> {
         Decoder {
-            buf,
+            windows,
             current_ptr: start_ptr,
+            missing: None,
+        }
+    }
+
+    /// Whether decoding ran past the data it was given, i.e. whether it may
+    /// succeed with more of the data section.
+    pub fn is_truncated(&self) -> bool {
+        self.missing.is_some()
+    }
+
+    /// The offset and size of the read that ran past the data the decoder
+    /// was given, if any.
+    pub fn missing(&self) -> Option<(usize, usize)> {
+        self.missing
+    }
+
+    /// Reads the encoding of the value at the current position without
+    /// following its pointers.
+    pub fn record_layout(&mut self) -> DecodeResult<RecordLayout> {
+        let start = self.current_ptr;
+        let mut layout = RecordLayout::default();
+        let (size, type_num) = self.size_and_type()?;
+        if type_num == 7 {
+            for _ in 0..size {
+                let entry_start = self.current_ptr;
+                // Keys are often pointers to a shared string
+                self.skip_value(&mut layout.pointers)?;
+                let value_start = self.current_ptr;
+                self.current_ptr = entry_start;
+                let key = String::deserialize(&mut *self)?;
+                self.current_ptr = value_start;
+                self.skip_value(&mut layout.pointers)?;
+                layout.keys.push((key, self.current_ptr - entry_start));
+            }
+        } else {
+            self.current_ptr = start;
+            self.skip_value(&mut layout.pointers)?;
+        }
+        layout.size = self.current_ptr - start;
+        Ok(layout)
+    }
+
+    /// Whether the value at the current position, or the one it points to,
+    /// is an empty map or array.
+    pub fn is_empty_value(&mut self) -> DecodeResult<bool> {
+        let (mut size, mut type_num) = self.size_and_type()?;
+        if type_num == 1 {
+            self.current_ptr = self.decode_pointer(size)?;
+            (size, type_num) = self.size_and_type()?;
+        }
+        Ok(size == 0 && (type_num == 7 || type_num == 11))
+    }
+
+    // Moves past the value at the current position, collecting the targets
+    // of its pointers instead of following them
+    fn skip_value(&mut self, pointers: &mut Vec<usize>) -> DecodeResult<()> {
+        let (size, type_num) = self.size_and_type()?;
+        match type_num {
+            1 => pointers.push(self.decode_pointer(size)?),
+            7 => {
+                for _ in 0..size * 2 {
+                    self.skip_value(pointers)?;
+                }
+            }
+            11 => {
+                for _ in 0..size {
+                    self.skip_value(pointers)?;
+                }
+            }
+            // The size is the value
+            14 => {}
+            2..=6 | 8..=10 | 15 => {
+                self.read_bytes(size)?;
+            }
+            u => {
+                return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                    "Unknown data type: {:?}",
+                    u
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, size: usize) -> DecodeResult<&'de [u8]> {
+        let new_offset = self.current_ptr + size;
+        for &(start, buf) in &self.windows {
+            if start <= self.current_ptr && new_offset <= start + buf.len() {
+                let bytes = &buf[self.current_ptr - start..new_offset - start];
+                self.current_ptr = new_offset;
+                return Ok(bytes);
+            }
         }
+        self.missing.get_or_insert((self.current_ptr, size));
+        Err(MaxMindDBError::InvalidDatabaseError(
+            "unexpected end of data".to_owned(),
+        ))
     }
 
-    fn eat_byte(&mut self) -> u8 {
-        let b = self.buf[self.current_ptr];
-        self.current_ptr += 1;
-        b
+    fn eat_byte(&mut self) -> DecodeResult<u8> {
+        Ok(self.read_bytes(1)?[0])
     }
 
-    fn size_from_ctrl_byte(&mut self, ctrl_byte: u8, type_num: u8) -> usize {
+    fn size_from_ctrl_byte(&mut self, ctrl_byte: u8, type_num: u8) -> DecodeResult<usize> {
         let size = (ctrl_byte & 0x1f) as usize;
         // extended
         if type_num == 0 {
-            return size;
+            return Ok(size);
         }
 
-        let bytes_to_read = if size > 28 { size - 28 } else { 0 };
+        let bytes_to_read = size.saturating_sub(28);
+        let size_bytes = self.read_bytes(bytes_to_read)?;
 
-        let new_offset = self.current_ptr + bytes_to_read;
-        let size_bytes = &self.buf[self.current_ptr..new_offset];
-        self.current_ptr = new_offset;
-
-        match size {
+        Ok(match size {
             s if s < 29 => s,
             29 => 29_usize + size_bytes[0] as usize,
             30 => 285_usize + to_usize(0, size_bytes),
             _ => 65_821_usize + to_usize(0, size_bytes),
-        }
+        })
     }
 
-    fn size_and_type(&mut self) -> (usize, u8) {
-        let ctrl_byte = self.eat_byte();
+    fn size_and_type(&mut self) -> DecodeResult<(usize, u8)> {
+        let ctrl_byte = self.eat_byte()?;
         let mut type_num = ctrl_byte >> 5;
         // Extended type
         if type_num == 0 {
-            type_num = self.eat_byte() + 7;
+            type_num = self.eat_byte()? + 7;
         }
-        let size = self.size_from_ctrl_byte(ctrl_byte, type_num);
-        (size, type_num)
+        let size = self.size_from_ctrl_byte(ctrl_byte, type_num)?;
+        Ok((size, type_num))
     }
 
     fn decode_any<V: Visitor<'de>>(&mut self, visitor: V) -> DecodeResult<V::Value> {
@@ -104,10 +217,10 @@ impl<'de> Decoder<'de> {
     }
 
     fn decode_any_value(&mut self) -> DecodeResult<Value<'_, 'de>> {
-        let (size, type_num) = self.size_and_type();
+        let (size, type_num) = self.size_and_type()?;
         Ok(match type_num {
             1 => {
-                let new_ptr = self.decode_pointer(size);
+                let new_ptr = self.decode_pointer(size)?;
                 let prev_ptr = self.current_ptr;
                 self.current_ptr = new_ptr;
 
@@ -121,14 +234,7 @@ impl<'de> Decoder<'de> {
             7 => self.decode_map(size),
             8 => Value::I32(self.decode_int(size)?),
             9 => Value::U64(self.decode_uint64(size)?),
-            10 => {
-                serde_if_integer128! {
-                    return Ok(Value::U128(self.decode_uint128(size)?));
-                }
-
-                #[allow(unreachable_code)]
-                Value::Bytes(self.decode_bytes(size)?)
-            }
+            10 => Value::U128(self.decode_uint128(size)?),
             11 => self.decode_array(size),
             14 => Value::Bool(self.decode_bool(size)?),
             15 => Value::F32(self.decode_float(size)?),
@@ -159,39 +265,29 @@ impl<'de> Decoder<'de> {
     }
 
     fn decode_bytes(&mut self, size: usize) -> DecodeResult<&'de [u8]> {
-        let new_offset = self.current_ptr + size;
-        let u8_slice = &self.buf[self.current_ptr..new_offset];
-        self.current_ptr = new_offset;
-
-        Ok(u8_slice)
+        self.read_bytes(size)
     }
 
     fn decode_float(&mut self, size: usize) -> DecodeResult<f32> {
-        let new_offset = self.current_ptr + size;
-        let value: [u8; 4] = self.buf[self.current_ptr..new_offset]
-            .try_into()
-            .map_err(|_| {
-                MaxMindDBError::InvalidDatabaseError(format!(
-                    "float of size {:?}",
-                    new_offset - self.current_ptr
-                ))
-            })?;
-        self.current_ptr = new_offset;
+        if size != 4 {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "float of size {:?}",
+                size
+            )));
+        }
+        let value: [u8; 4] = self.read_bytes(size)?.try_into().expect("size checked above");
         let float_value = f32::from_be_bytes(value);
         Ok(float_value)
     }
 
     fn decode_double(&mut self, size: usize) -> DecodeResult<f64> {
-        let new_offset = self.current_ptr + size;
-        let value: [u8; 8] = self.buf[self.current_ptr..new_offset]
-            .try_into()
-            .map_err(|_| {
-                MaxMindDBError::InvalidDatabaseError(format!(
-                    "double of size {:?}",
-                    new_offset - self.current_ptr
-                ))
-            })?;
-        self.current_ptr = new_offset;
+        if size != 8 {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "double of size {:?}",
+                size
+            )));
+        }
+        let value: [u8; 8] = self.read_bytes(size)?.try_into().expect("size checked above");
         let float_value = f64::from_be_bytes(value);
         Ok(float_value)
     }
@@ -199,12 +295,10 @@ impl<'de> Decoder<'de> {
     fn decode_uint64(&mut self, size: usize) -> DecodeResult<u64> {
         match size {
             s if s <= 8 => {
-                let new_offset = self.current_ptr + size;
-
-                let value = self.buf[self.current_ptr..new_offset]
+                let value = self
+                    .read_bytes(size)?
                     .iter()
                     .fold(0_u64, |acc, &b| (acc << 8) | u64::from(b));
-                self.current_ptr = new_offset;
                 Ok(value)
             }
             s => Err(MaxMindDBError::InvalidDatabaseError(format!(
@@ -214,38 +308,29 @@ impl<'de> Decoder<'de> {
         }
     }
 
-    serde_if_integer128! {
-        fn decode_uint128(
-            &mut self,
-            size: usize,
-        ) -> DecodeResult<u128> {
-            match size {
-                s if s <= 16 => {
-                    let new_offset = self.current_ptr + size;
-
-                    let value = self.buf[self.current_ptr..new_offset]
-                        .iter()
-                        .fold(0_u128, |acc, &b| (acc << 8) | u128::from(b));
-                    self.current_ptr = new_offset;
-                    Ok(value)
-                }
-                s => Err(MaxMindDBError::InvalidDatabaseError(format!(
-                    "u128 of size {:?}",
-                    s
-                ))),
+    fn decode_uint128(&mut self, size: usize) -> DecodeResult<u128> {
+        match size {
+            s if s <= 16 => {
+                let value = self
+                    .read_bytes(size)?
+                    .iter()
+                    .fold(0_u128, |acc, &b| (acc << 8) | u128::from(b));
+                Ok(value)
             }
+            s => Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "u128 of size {:?}",
+                s
+            ))),
         }
     }
 
     fn decode_uint32(&mut self, size: usize) -> DecodeResult<u32> {
         match size {
             s if s <= 4 => {
-                let new_offset = self.current_ptr + size;
-
-                let value = self.buf[self.current_ptr..new_offset]
+                let value = self
+                    .read_bytes(size)?
                     .iter()
                     .fold(0_u32, |acc, &b| (acc << 8) | u32::from(b));
-                self.current_ptr = new_offset;
                 Ok(value)
             }
             s => Err(MaxMindDBError::InvalidDatabaseError(format!(
@@ -258,12 +343,10 @@ impl<'de> Decoder<'de> {
     fn decode_uint16(&mut self, size: usize) -> DecodeResult<u16> {
         match size {
             s if s <= 2 => {
-                let new_offset = self.current_ptr + size;
-
-                let value = self.buf[self.current_ptr..new_offset]
+                let value = self
+                    .read_bytes(size)?
                     .iter()
                     .fold(0_u16, |acc, &b| (acc << 8) | u16::from(b));
-                self.current_ptr = new_offset;
                 Ok(value)
             }
             s => Err(MaxMindDBError::InvalidDatabaseError(format!(
@@ -276,12 +359,10 @@ impl<'de> Decoder<'de> {
     fn decode_int(&mut self, size: usize) -> DecodeResult<i32> {
         match size {
             s if s <= 4 => {
-                let new_offset = self.current_ptr + size;
-
-                let value = self.buf[self.current_ptr..new_offset]
+                let value = self
+                    .read_bytes(size)?
                     .iter()
                     .fold(0_i32, |acc, &b| (acc << 8) | i32::from(b));
-                self.current_ptr = new_offset;
                 Ok(value)
             }
             s => Err(MaxMindDBError::InvalidDatabaseError(format!(
@@ -298,12 +379,10 @@ impl<'de> Decoder<'de> {
         })
     }
 
-    fn decode_pointer(&mut self, size: usize) -> usize {
+    fn decode_pointer(&mut self, size: usize) -> DecodeResult<usize> {
         let pointer_value_offset = [0, 0, 2048, 526_336, 0];
         let pointer_size = ((size >> 3) & 0x3) + 1;
-        let new_offset = self.current_ptr + pointer_size;
-        let pointer_bytes = &self.buf[self.current_ptr..new_offset];
-        self.current_ptr = new_offset;
+        let pointer_bytes = self.read_bytes(pointer_size)?;
 
         let base = if pointer_size == 4 {
             0
@@ -312,16 +391,14 @@ impl<'de> Decoder<'de> {
         };
         let unpacked = to_usize(base, pointer_bytes);
 
-        unpacked + pointer_value_offset[pointer_size]
+        Ok(unpacked + pointer_value_offset[pointer_size])
     }
 
     #[cfg(feature = "unsafe-str-decode")]
     fn decode_string(&mut self, size: usize) -> DecodeResult<&'de str> {
         use std::str::from_utf8_unchecked;
 
-        let new_offset: usize = self.current_ptr + size;
-        let bytes = &self.buf[self.current_ptr..new_offset];
-        self.current_ptr = new_offset;
+        let bytes = self.read_bytes(size)?;
         // SAFETY:
         // A corrupt maxminddb will cause undefined behaviour.
         // If the caller has verified the integrity of their database and trusts their upstream
@@ -336,9 +413,7 @@ impl<'de> Decoder<'de> {
     fn decode_string(&mut self, size: usize) -> DecodeResult<&'de str> {
         use std::str::from_utf8;
 
-        let new_offset: usize = self.current_ptr + size;
-        let bytes = &self.buf[self.current_ptr..new_offset];
-        self.current_ptr = new_offset;
+        let bytes = self.read_bytes(size)?;
         match from_utf8(bytes) {
             Ok(v) => Ok(v),
             Err(_) => Err(MaxMindDBError::InvalidDatabaseError(
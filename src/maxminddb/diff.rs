@@ -0,0 +1,224 @@
+//! Comparison of two versions of a database.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut old = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-Country-Test.mmdb").await.unwrap();
+//! let mut new = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+//!
+//! let mut summary = maxminddb::diff::DiffSummary::default();
+//! let mut diff = maxminddb::diff::diff(&mut old, &mut new).await.unwrap();
+//! while let Some(difference) = diff.next().await.unwrap() {
+//!     summary.record(&difference);
+//! }
+//! print!("{:?}", summary);
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use ipnetwork::IpNetwork;
+use serde::Serialize;
+use super::{bytes_and_prefix_to_net, MaxMindDBError, ReadAt, Reader, Value};
+
+/// A network whose record differs between the two databases. `None` means
+/// the network has no record in that database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub ip_net: IpNetwork,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Counts of the differences between two databases.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiffSummary {
+    /// Networks only present in the new database
+    pub added: usize,
+    /// Networks only present in the old database
+    pub removed: usize,
+    /// Networks present in both databases with different records
+    pub changed: usize,
+    /// Number of changed networks per top-level field of the record
+    pub fields: BTreeMap<String, usize>,
+    /// Changed networks whose old or new record isn't a map, so the change
+    /// isn't counted per field
+    pub non_map_records: usize,
+}
+
+impl DiffSummary {
+    pub fn record(&mut self, difference: &Difference) {
+        match (&difference.old, &difference.new) {
+            (None, Some(_)) => self.added += 1,
+            (Some(_), None) => self.removed += 1,
+            (Some(old), Some(new)) => {
+                self.changed += 1;
+                match changed_fields(old, new) {
+                    Some(fields) => {
+                        for field in fields {
+                            *self.fields.entry(field).or_insert(0) += 1;
+                        }
+                    }
+                    None => self.non_map_records += 1,
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+// The top-level fields that differ, `None` unless both records are maps
+fn changed_fields(old: &Value, new: &Value) -> Option<Vec<String>> {
+    match (old, new) {
+        (Value::Map(old), Value::Map(new)) => {
+            let mut fields: Vec<String> = old
+                .iter()
+                .filter(|(k, v)| new.get(*k) != Some(v))
+                .map(|(k, _)| k.clone())
+                .collect();
+            fields.extend(new.keys().filter(|k| !old.contains_key(*k)).cloned());
+            fields.sort();
+            Some(fields)
+        }
+        _ => None,
+    }
+}
+
+struct DiffNode {
+    old: usize,
+    new: usize,
+    ip_bytes: Vec<u8>,
+    prefix_len: usize,
+}
+
+/// Walks the search trees of two databases side by side, see [`diff`].
+pub struct Diff<'a, A: ReadAt, B: ReadAt> {
+    old: &'a mut Reader<A>,
+    new: &'a mut Reader<B>,
+    stack: Vec<DiffNode>,
+    // The same record is usually compared against many smaller networks in a
+    // row, so keep the last decoded one of each side around.
+    old_cache: Option<(usize, Value)>,
+    new_cache: Option<(usize, Value)>,
+}
+
+/// Compare the networks of two databases of the same IP version.
+///
+/// The returned [`Diff`] yields every network whose record was added,
+/// removed or changed. Networks are reported at the granularity of the more
+/// specific of the two trees. The IPv4 aliases of IPv6 databases are
+/// skipped where both databases have them, elsewhere the IPv4 subtree is
+/// compared with what the other database has at that network.
+pub async fn diff<'a, A, B>(old: &'a mut Reader<A>, new: &'a mut Reader<B>) -> Result<Diff<'a, A, B>, MaxMindDBError>
+where
+    A: ReadAt,
+    B: ReadAt,
+{
+    if old.metadata.ip_version != new.metadata.ip_version {
+        return Err(MaxMindDBError::InvalidDatabaseError(format!(
+            "cannot compare an IPv{} database with an IPv{} database",
+            old.metadata.ip_version, new.metadata.ip_version
+        )));
+    }
+    let ip_bytes = if old.metadata.ip_version == 6 { vec![0; 16] } else { vec![0; 4] };
+
+    Ok(Diff {
+        old,
+        new,
+        stack: vec![DiffNode {
+            old: 0,
+            new: 0,
+            ip_bytes,
+            prefix_len: 0,
+        }],
+        old_cache: None,
+        new_cache: None,
+    })
+}
+
+impl<'a, A: ReadAt, B: ReadAt> Diff<'a, A, B> {
+    /// Returns the next network that differs, or `None` once both trees have
+    /// been walked.
+    pub async fn next(&mut self) -> Result<Option<Difference>, MaxMindDBError> {
+        let old_count = self.old.metadata.node_count as usize;
+        let new_count = self.new.metadata.node_count as usize;
+
+        while let Some(current) = self.stack.pop() {
+            if self.old.is_ipv4_alias(current.old, &current.ip_bytes)
+                && self.new.is_ipv4_alias(current.new, &current.ip_bytes)
+            {
+                continue;
+            }
+
+            if current.old < old_count || current.new < new_count {
+                // At least one side has a subtree here, walk it in order. A
+                // record on the other side covers both halves.
+                let bit_count = current.ip_bytes.len() * 8;
+                let mut right_ip_bytes = current.ip_bytes.clone();
+                right_ip_bytes[current.prefix_len >> 3] |= 1 << ((bit_count - current.prefix_len - 1) % 8);
+
+                let (old_left, old_right) = if current.old < old_count {
+                    (
+                        self.old.read_node(current.old, 0).await?,
+                        self.old.read_node(current.old, 1).await?,
+                    )
+                } else {
+                    (current.old, current.old)
+                };
+                let (new_left, new_right) = if current.new < new_count {
+                    (
+                        self.new.read_node(current.new, 0).await?,
+                        self.new.read_node(current.new, 1).await?,
+                    )
+                } else {
+                    (current.new, current.new)
+                };
+
+                self.stack.push(DiffNode {
+                    old: old_right,
+                    new: new_right,
+                    ip_bytes: right_ip_bytes,
+                    prefix_len: current.prefix_len + 1,
+                });
+                self.stack.push(DiffNode {
+                    old: old_left,
+                    new: new_left,
+                    ip_bytes: current.ip_bytes,
+                    prefix_len: current.prefix_len + 1,
+                });
+                continue;
+            }
+
+            let old = if current.old > old_count {
+                Some(cached_decode(self.old, &mut self.old_cache, current.old).await?)
+            } else {
+                None
+            };
+            let new = if current.new > new_count {
+                Some(cached_decode(self.new, &mut self.new_cache, current.new).await?)
+            } else {
+                None
+            };
+            if old != new {
+                let ip_net = bytes_and_prefix_to_net(&current.ip_bytes, current.prefix_len as u8)?;
+                return Ok(Some(Difference { ip_net, old, new }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+async fn cached_decode<S: ReadAt>(
+    reader: &mut Reader<S>,
+    cache: &mut Option<(usize, Value)>,
+    pointer: usize,
+) -> Result<Value, MaxMindDBError> {
+    match cache {
+        Some((cached, value)) if *cached == pointer => Ok(value.clone()),
+        _ => {
+            let value: Value = reader.decode_data(pointer).await?;
+            *cache = Some((pointer, value.clone()));
+            Ok(value)
+        }
+    }
+}
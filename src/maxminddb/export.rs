@@ -0,0 +1,122 @@
+//! Streaming exports of the networks of a database to CSV and JSON Lines.
+//!
+//! Networks are visited with [`Reader::within`], so only one record is held
+//! in memory at a time.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+//!
+//! let mut csv = Vec::new();
+//! maxminddb::export::write_csv(&mut reader, None, &["country.iso_code", "city.names.en"], &mut csv)
+//!     .await
+//!     .unwrap();
+//! print!("{}", String::from_utf8(csv).unwrap());
+//! # }
+//! ```
+
+use std::io::Write;
+
+use ipnetwork::IpNetwork;
+use super::{MaxMindDBError, ReadAt, Reader, Value};
+
+/// Writes one CSV row per network in `cidr`, or in the whole database when
+/// `cidr` is `None`.
+///
+/// The first column is the network, followed by one column per entry of
+/// `columns`. Columns are dot separated paths into the record, see
+/// [`Value::get_path`]. Missing values are left empty and maps or arrays are
+/// written as JSON. A header row with the column paths is written first.
+///
+/// Returns the number of networks written.
+pub async fn write_csv<S, W>(
+    reader: &mut Reader<S>,
+    cidr: Option<IpNetwork>,
+    columns: &[&str],
+    writer: W,
+) -> Result<usize, MaxMindDBError>
+where
+    S: ReadAt,
+    W: Write,
+{
+    let mut csv = csv::Writer::from_writer(writer);
+    let header = std::iter::once("network").chain(columns.iter().copied());
+    csv.write_record(header).map_err(csv_error)?;
+
+    let cidr = cidr.unwrap_or_else(|| whole_database(reader));
+    let mut count = 0;
+    let mut iter = reader.within::<Value>(cidr).await?;
+    while let Some(item) = iter.next().await? {
+        let mut row = Vec::with_capacity(columns.len() + 1);
+        row.push(item.ip_net.to_string());
+        for column in columns {
+            row.push(match item.info.get_path(column) {
+                Some(value) => to_cell(value)?,
+                None => String::new(),
+            });
+        }
+        csv.write_record(&row).map_err(csv_error)?;
+        count += 1;
+    }
+    csv.flush()?;
+    Ok(count)
+}
+
+/// Writes one JSON object per line for every network in `cidr`, or in the
+/// whole database when `cidr` is `None`. Each object has a `network` and a
+/// `record` member.
+///
+/// Returns the number of networks written.
+pub async fn write_jsonl<S, W>(
+    reader: &mut Reader<S>,
+    cidr: Option<IpNetwork>,
+    mut writer: W,
+) -> Result<usize, MaxMindDBError>
+where
+    S: ReadAt,
+    W: Write,
+{
+    let cidr = cidr.unwrap_or_else(|| whole_database(reader));
+    let mut count = 0;
+    let mut iter = reader.within::<Value>(cidr).await?;
+    while let Some(item) = iter.next().await? {
+        let line = serde_json::json!({
+            "network": item.ip_net.to_string(),
+            "record": item.info,
+        });
+        serde_json::to_writer(&mut writer, &line).map_err(json_error)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn whole_database<S: ReadAt>(reader: &Reader<S>) -> IpNetwork {
+    let network = if reader.metadata.ip_version == 6 { "::/0" } else { "0.0.0.0/0" };
+    network.parse().expect("valid network")
+}
+
+fn to_cell(value: &Value) -> Result<String, MaxMindDBError> {
+    Ok(match value {
+        Value::String(s) => s.clone(),
+        Value::Double(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Uint16(v) => v.to_string(),
+        Value::Uint32(v) => v.to_string(),
+        Value::Int32(v) => v.to_string(),
+        Value::Uint64(v) => v.to_string(),
+        Value::Uint128(v) => v.to_string(),
+        Value::Boolean(v) => v.to_string(),
+        Value::Bytes(_) | Value::Map(_) | Value::Array(_) => serde_json::to_string(value).map_err(json_error)?,
+    })
+}
+
+fn csv_error(err: csv::Error) -> MaxMindDBError {
+    MaxMindDBError::IoError(err.to_string())
+}
+
+fn json_error(err: serde_json::Error) -> MaxMindDBError {
+    MaxMindDBError::IoError(err.to_string())
+}
@@ -0,0 +1,247 @@
+//! Compiling CSV and JSON Lines tables of networks into a new database.
+//!
+//! Each input row holds a network and some fields. A [`Schema`] selects the
+//! fields to import and the MaxMind DB type to store them as, and the rows are
+//! inserted into a [`Writer`].
+//!
+//! ```
+//! use maxminddb::import::{import_csv, FieldType, Schema};
+//! use maxminddb::writer::Writer;
+//!
+//! let input = "network,asn,name\n1.1.1.0/24,13335,Cloudflare\n";
+//! let schema = Schema::new()
+//!     .field("autonomous_system_number", "asn", FieldType::Uint32)
+//!     .field("autonomous_system_organization", "name", FieldType::String);
+//!
+//! let mut writer = Writer::new("My-ASN-DB", 6).unwrap();
+//! import_csv(&mut writer, &schema, input.as_bytes()).unwrap();
+//!
+//! let mut buf = Vec::new();
+//! writer.write_to(&mut buf).unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read};
+use std::str::FromStr;
+
+use ipnetwork::IpNetwork;
+
+use super::writer::Writer;
+use super::{MaxMindDBError, Value};
+
+/// The MaxMind DB type an imported field is stored as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    Uint16,
+    Uint32,
+    String,
+    Bool,
+    Double,
+}
+
+impl FromStr for FieldType {
+    type Err = MaxMindDBError;
+
+    fn from_str(s: &str) -> Result<FieldType, MaxMindDBError> {
+        match s {
+            "uint16" => Ok(FieldType::Uint16),
+            "uint32" => Ok(FieldType::Uint32),
+            "string" | "utf8_string" => Ok(FieldType::String),
+            "bool" | "boolean" => Ok(FieldType::Bool),
+            "double" => Ok(FieldType::Double),
+            t => Err(MaxMindDBError::DecodingError(format!("unknown field type: {}", t))),
+        }
+    }
+}
+
+impl FieldType {
+    fn parse(self, text: &str) -> Option<Value> {
+        match self {
+            FieldType::Uint16 => text.parse().ok().map(Value::Uint16),
+            FieldType::Uint32 => text.parse().ok().map(Value::Uint32),
+            FieldType::String => Some(Value::String(text.to_owned())),
+            FieldType::Bool => match text {
+                "true" | "1" => Some(Value::Boolean(true)),
+                "false" | "0" => Some(Value::Boolean(false)),
+                _ => None,
+            },
+            FieldType::Double => text.parse().ok().map(Value::Double),
+        }
+    }
+
+    fn parse_json(self, json: &serde_json::Value) -> Option<Value> {
+        match (self, json) {
+            (_, serde_json::Value::String(s)) => self.parse(s),
+            (FieldType::Uint16, serde_json::Value::Number(n)) => {
+                n.as_u64().and_then(|n| n.try_into().ok()).map(Value::Uint16)
+            }
+            (FieldType::Uint32, serde_json::Value::Number(n)) => {
+                n.as_u64().and_then(|n| n.try_into().ok()).map(Value::Uint32)
+            }
+            (FieldType::Double, serde_json::Value::Number(n)) => n.as_f64().map(Value::Double),
+            (FieldType::Bool, serde_json::Value::Bool(b)) => Some(Value::Boolean(*b)),
+            _ => None,
+        }
+    }
+}
+
+/// Maps input columns to the fields of the records of the new database.
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    path: String,
+    column: String,
+    field_type: FieldType,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Stores `column` of the input as `field_type` at `path` in the record.
+    /// Paths are dot separated to build nested maps, e.g. `country.iso_code`.
+    pub fn field(mut self, path: &str, column: &str, field_type: FieldType) -> Schema {
+        self.fields.push(Field {
+            path: path.to_owned(),
+            column: column.to_owned(),
+            field_type,
+        });
+        self
+    }
+
+    fn record<F>(&self, line: usize, mut get: F) -> Result<Value, MaxMindDBError>
+    where
+        F: FnMut(&Field) -> Option<Result<Value, String>>,
+    {
+        let mut record = BTreeMap::new();
+        for field in &self.fields {
+            match get(field) {
+                Some(Ok(value)) => insert_path(&mut record, &field.path, value).map_err(|e| {
+                    MaxMindDBError::DecodingError(format!("line {}: {}", line, e))
+                })?,
+                Some(Err(invalid)) => {
+                    return Err(MaxMindDBError::DecodingError(format!(
+                        "line {}: invalid {:?} value `{}` in column {}",
+                        line, field.field_type, invalid, field.column
+                    )))
+                }
+                // Missing values are left out of the record
+                None => {}
+            }
+        }
+        Ok(Value::Map(record))
+    }
+}
+
+fn insert_path(record: &mut BTreeMap<String, Value>, path: &str, value: Value) -> Result<(), String> {
+    let mut map = record;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if keys.peek().is_none() {
+            map.insert(key.to_owned(), value);
+            return Ok(());
+        }
+        map = match map.entry(key.to_owned()).or_insert_with(|| Value::Map(BTreeMap::new())) {
+            Value::Map(m) => m,
+            _ => return Err(format!("{} is not a map in field {}", key, path)),
+        };
+    }
+    Ok(())
+}
+
+fn parse_network(line: usize, network: &str) -> Result<IpNetwork, MaxMindDBError> {
+    network
+        .trim()
+        .parse()
+        .map_err(|e| MaxMindDBError::InvalidNetworkError(format!("line {}: {}: {}", line, network, e)))
+}
+
+/// Inserts every row of a CSV table into `writer`.
+///
+/// The first row names the columns, one of them must be `network`. Empty
+/// cells are left out of the record. Returns the number of networks
+/// inserted.
+pub fn import_csv<R: Read>(writer: &mut Writer, schema: &Schema, input: R) -> Result<usize, MaxMindDBError> {
+    let mut csv = csv::Reader::from_reader(input);
+    let headers = csv.headers().map_err(csv_error)?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| MaxMindDBError::DecodingError(format!("missing column: {}", name)))
+    };
+    let network_column = column("network")?;
+    let columns = schema
+        .fields
+        .iter()
+        .map(|field| column(&field.column))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut count = 0;
+    for (i, row) in csv.records().enumerate() {
+        let row = row.map_err(csv_error)?;
+        // The header is line 1
+        let line = i + 2;
+        let network = parse_network(line, &row[network_column])?;
+        let mut columns = columns.iter();
+        let record = schema.record(line, |field| {
+            let text = &row[*columns.next().expect("one column per field")];
+            if text.is_empty() {
+                return None;
+            }
+            Some(field.field_type.parse(text).ok_or_else(|| text.to_owned()))
+        })?;
+        writer.insert(network, record)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Inserts every line of a JSON Lines file into `writer`.
+///
+/// Each line is an object with a `network` member. Columns are looked up as
+/// members of the object, or as dot separated paths into nested objects.
+/// Missing and `null` values are left out of the record. Returns the number
+/// of networks inserted.
+pub fn import_jsonl<R: BufRead>(writer: &mut Writer, schema: &Schema, input: R) -> Result<usize, MaxMindDBError> {
+    let mut count = 0;
+    for (i, text) in input.lines().enumerate() {
+        let text = text?;
+        let line = i + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let object: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| MaxMindDBError::DecodingError(format!("line {}: {}", line, e)))?;
+        let network = match object.get("network") {
+            Some(serde_json::Value::String(network)) => parse_network(line, network)?,
+            _ => {
+                return Err(MaxMindDBError::DecodingError(format!(
+                    "line {}: missing network",
+                    line
+                )))
+            }
+        };
+        let record = schema.record(line, |field| {
+            let json = object
+                .get(&field.column)
+                .or_else(|| field.column.split('.').try_fold(&object, |json, key| json.get(key)))?;
+            if json.is_null() {
+                return None;
+            }
+            Some(field.field_type.parse_json(json).ok_or_else(|| json.to_string()))
+        })?;
+        writer.insert(network, record)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn csv_error(err: csv::Error) -> MaxMindDBError {
+    MaxMindDBError::IoError(err.to_string())
+}
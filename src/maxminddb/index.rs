@@ -0,0 +1,164 @@
+//! In-memory indexes from a field of the records to their networks, for
+//! repeated reverse queries such as the networks of an ASN or a country.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+//!
+//! let index = reader.index_by::<String>("country.iso_code").await.unwrap();
+//! for network in index.get("SE") {
+//!     println!("{}", network);
+//! }
+//! # }
+//! ```
+//!
+//! [`Reader::cached_index`] builds an index lazily instead, on its first
+//! use, and keeps it on the reader for the following ones.
+
+use std::any::TypeId;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, PoisonError};
+
+use ipnetwork::IpNetwork;
+use serde::de::DeserializeOwned;
+
+use super::{MaxMindDBError, NetworksOptions, ReadAt, Reader, Value};
+
+/// The networks of a database grouped by the value of a field of their
+/// records, see [`Reader::index_by`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Index<K> {
+    networks: BTreeMap<K, Vec<IpNetwork>>,
+}
+
+impl<K: Ord> Index<K> {
+    /// The networks whose field is `key`, sorted by address with the IPv4
+    /// networks first.
+    pub fn get<Q>(&self, key: &Q) -> &[IpNetwork]
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.networks
+            .get(key)
+            .map_or(&[], |networks| networks.as_slice())
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.networks.contains_key(key)
+    }
+
+    /// The distinct values of the field, in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.networks.keys()
+    }
+
+    /// The values of the field with their networks, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &[IpNetwork])> {
+        self.networks
+            .iter()
+            .map(|(key, networks)| (key, networks.as_slice()))
+    }
+
+    /// The number of distinct values of the field.
+    pub fn len(&self) -> usize {
+        self.networks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+impl<S: ReadAt> Reader<S> {
+    /// Builds an index from the field at `path` of the records, e.g.
+    /// `country.iso_code` or `autonomous_system_number`, to the networks
+    /// with that value, see [`Value::get_path`] for the syntax. Networks
+    /// whose record has no such field are left out, and the IPv4 aliases of
+    /// IPv6 databases are skipped.
+    ///
+    /// The whole tree is walked, but each record is decoded once. Build the
+    /// index once, e.g. right after opening the database, and keep it for
+    /// the queries.
+    pub async fn index_by<K>(&mut self, path: &str) -> Result<Index<K>, MaxMindDBError>
+    where
+        K: DeserializeOwned + Ord,
+    {
+        // Group the networks by record first, so each record is decoded once
+        let options = NetworksOptions::new()
+            .skip_aliased_networks(true)
+            .include_empty_networks(true);
+        let mut iter = self.networks::<Value>(options).await?;
+        let mut by_record: HashMap<usize, Vec<IpNetwork>> = HashMap::new();
+        let mut records = Vec::new();
+        while let Some((ip_net, pointer)) = iter.inner.next_network().await? {
+            if let Some(pointer) = pointer {
+                by_record
+                    .entry(pointer)
+                    .or_insert_with(|| {
+                        records.push(pointer);
+                        Vec::new()
+                    })
+                    .push(ip_net);
+            }
+        }
+
+        let mut networks: BTreeMap<K, Vec<IpNetwork>> = BTreeMap::new();
+        for pointer in records {
+            let record: Value = self.decode_data(pointer).await?;
+            let value = match record.get_path(path) {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            let key = K::deserialize(value).map_err(|e| {
+                MaxMindDBError::DecodingError(format!("Couldn't decode {}: {}", path, e))
+            })?;
+            let record_networks = by_record.remove(&pointer).unwrap_or_default();
+            networks.entry(key).or_default().extend(record_networks);
+        }
+        // Networks of different records with the same value are interleaved
+        for list in networks.values_mut() {
+            list.sort_by_key(|network| (network.is_ipv6(), ip_octets(network), network.prefix()));
+        }
+        Ok(Index { networks })
+    }
+
+    /// Like [`Reader::index_by`], but the index is built on the first call
+    /// for `path` and `K` only, and the same index is returned by the
+    /// following ones. Clones of the reader share these indexes.
+    pub async fn cached_index<K>(&mut self, path: &str) -> Result<Arc<Index<K>>, MaxMindDBError>
+    where
+        K: DeserializeOwned + Ord + Send + Sync + 'static,
+    {
+        let key = (path.to_owned(), TypeId::of::<K>());
+        let cached = self
+            .indexes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .cloned();
+        if let Some(Ok(index)) = cached.map(|index| index.downcast::<Index<K>>()) {
+            return Ok(index);
+        }
+
+        let index = Arc::new(self.index_by::<K>(path).await?);
+        self.indexes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, index.clone());
+        Ok(index)
+    }
+}
+
+fn ip_octets(network: &IpNetwork) -> Vec<u8> {
+    match network {
+        IpNetwork::V4(net) => net.network().octets().to_vec(),
+        IpNetwork::V6(net) => net.network().octets().to_vec(),
+    }
+}
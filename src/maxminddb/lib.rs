@@ -1,15 +1,32 @@
 #![deny(trivial_casts, trivial_numeric_casts, unused_import_braces)]
 
-use std::collections::BTreeMap;
+use std::any::{Any, TypeId};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Display, Formatter};
-use std::io;
-use std::net::IpAddr;
+use std::io::{self, Cursor};
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use ipnetwork::IpNetwork;
 use serde::de::DeserializeOwned;
-use serde::{de, Deserialize};
-use source::Source;
-use tokio::fs::File;
-use tokio::io::{AsyncRead, AsyncSeek};
+use serde::{de, Deserialize, Serialize};
+
+pub use options::{NetworksOptions, ReaderOptions};
+#[cfg(feature = "futures-io")]
+pub use source::FuturesIo;
+#[cfg(feature = "http")]
+pub use source::HttpFile;
+#[cfg(feature = "object-store")]
+pub use source::ObjectStoreFile;
+#[cfg(feature = "tokio")]
+pub use source::{PreadFile, TokioIo};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use source::UringFile;
+pub use source::{ReadAt, Source};
+pub use value::Value;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum MaxMindDBError {
@@ -19,6 +36,7 @@ pub enum MaxMindDBError {
     MapError(String),
     DecodingError(String),
     InvalidNetworkError(String),
+    IpVersionMismatchError(String),
 }
 
 impl From<io::Error> for MaxMindDBError {
@@ -43,6 +61,9 @@ impl Display for MaxMindDBError {
             MaxMindDBError::InvalidNetworkError(msg) => {
                 write!(fmt, "InvalidNetworkError: {}", msg)?
             }
+            MaxMindDBError::IpVersionMismatchError(msg) => {
+                write!(fmt, "IpVersionMismatchError: {}", msg)?
+            }
         }
         Ok(())
     }
@@ -57,7 +78,7 @@ impl de::Error for MaxMindDBError {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct Metadata {
     pub binary_format_major_version: u16,
     pub binary_format_minor_version: u16,
@@ -68,51 +89,464 @@ pub struct Metadata {
     pub languages: Vec<String>,
     pub node_count: u32,
     pub record_size: u16,
+    /// Any other entries of the metadata map, e.g. custom keys added by the
+    /// tool that built the database.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// The IP version of the addresses in the search tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+/// The size of a search tree record in bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordSize {
+    Bits24,
+    Bits28,
+    Bits32,
+}
+
+impl RecordSize {
+    /// The number of bits of a record.
+    pub fn bits(self) -> usize {
+        match self {
+            RecordSize::Bits24 => 24,
+            RecordSize::Bits28 => 28,
+            RecordSize::Bits32 => 32,
+        }
+    }
+
+    /// The number of bytes of a node, i.e. of two records.
+    pub fn node_bytes(self) -> usize {
+        self.bits() / 4
+    }
+}
+
+impl Metadata {
+    /// The time the database was built at.
+    pub fn build_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.build_epoch)
+    }
+
+    /// The IP version of the search tree, or an `InvalidDatabaseError` if
+    /// `ip_version` is neither 4 nor 6.
+    pub fn ip_version(&self) -> Result<IpVersion, MaxMindDBError> {
+        match self.ip_version {
+            4 => Ok(IpVersion::V4),
+            6 => Ok(IpVersion::V6),
+            v => Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "unsupported ip_version: {}",
+                v
+            ))),
+        }
+    }
+
+    /// The size of the records of the search tree, or an
+    /// `InvalidDatabaseError` if `record_size` is not 24, 28 or 32.
+    pub fn record_size(&self) -> Result<RecordSize, MaxMindDBError> {
+        match self.record_size {
+            24 => Ok(RecordSize::Bits24),
+            28 => Ok(RecordSize::Bits28),
+            32 => Ok(RecordSize::Bits32),
+            s => Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "unsupported record_size: {}",
+                s
+            ))),
+        }
+    }
+
+    /// The description of the database in `language`, if there is one.
+    pub fn description_for(&self, language: &str) -> Option<&str> {
+        self.description.get(language).map(String::as_str)
+    }
+
+    /// The size of the search tree in bytes.
+    pub fn search_tree_size(&self) -> usize {
+        (self.node_count as usize) * (self.record_size as usize) / 4
+    }
+
+    /// Checks the metadata of a database whose metadata section starts
+    /// `metadata_offset` bytes into the file.
+    fn validate(&self, metadata_offset: usize) -> Result<(), MaxMindDBError> {
+        if self.binary_format_major_version != 2 {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "unsupported binary_format_major_version: {}",
+                self.binary_format_major_version
+            )));
+        }
+        self.record_size()?;
+        self.ip_version()?;
+        // The search tree and the data section separator precede the data
+        // section, which precedes the metadata
+        if self.search_tree_size() + 16 > metadata_offset {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "search tree of {} nodes does not fit in the {} bytes before the metadata",
+                self.node_count, metadata_offset
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An address that can be looked up, see [`Reader::lookup`].
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) are looked up as the IPv4
+/// address they map, so they find the same record as `a.b.c.d`. In IPv6
+/// databases their prefix length includes the 96 bits of `::ffff:0:0/96`,
+/// e.g. 120 where `a.b.c.d` has 24.
+pub trait IntoLookupAddr {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError>;
+}
+
+impl IntoLookupAddr for IpAddr {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        Ok(self)
+    }
+}
+
+impl IntoLookupAddr for Ipv4Addr {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        Ok(IpAddr::V4(self))
+    }
+}
+
+impl IntoLookupAddr for Ipv6Addr {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        IpAddr::V6(self).into_lookup_addr()
+    }
+}
+
+impl IntoLookupAddr for SocketAddr {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        self.ip().into_lookup_addr()
+    }
+}
+
+impl IntoLookupAddr for [u8; 4] {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        Ipv4Addr::from(self).into_lookup_addr()
+    }
+}
+
+impl IntoLookupAddr for [u8; 16] {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        Ipv6Addr::from(self).into_lookup_addr()
+    }
+}
+
+impl IntoLookupAddr for &str {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        self.parse::<IpAddr>()
+            .map_err(|_| MaxMindDBError::InvalidNetworkError(format!("invalid address: {}", self)))?
+            .into_lookup_addr()
+    }
+}
+
+impl IntoLookupAddr for &String {
+    fn into_lookup_addr(self) -> Result<IpAddr, MaxMindDBError> {
+        self.as_str().into_lookup_addr()
+    }
+}
+
+/// A network in the database together with its decoded record.
+#[derive(Debug)]
+pub struct WithinItem<T> {
+    pub ip_net: IpNetwork,
+    pub info: T,
+}
+
+/// A record of a node of the search tree, see [`Reader::node`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Record {
+    /// The number of the next node
+    Node(usize),
+    /// The end of a network without data
+    Empty,
+    /// The end of a network, with the offset of its record in the data
+    /// section
+    Data(usize),
+}
+
+#[derive(Debug)]
+struct WithinNode {
+    node: usize,
+    ip_bytes: Vec<u8>,
+    prefix_len: usize,
+}
+
+/// Walks the networks of the database contained in a CIDR, see [`Reader::within`].
+pub struct Within<'a, T: DeserializeOwned, S: ReadAt> {
+    reader: &'a mut Reader<S>,
+    node_count: usize,
+    stack: Vec<WithinNode>,
+    options: NetworksOptions,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T: DeserializeOwned, S: ReadAt> Within<'a, T, S> {
+    /// Returns the next network with its record, or `None` once the whole
+    /// CIDR has been visited.
+    pub async fn next(&mut self) -> Result<Option<WithinItem<T>>, MaxMindDBError> {
+        while let Some((ip_net, pointer)) = self.next_network().await? {
+            if let Some(pointer) = pointer {
+                let info = self.reader.decode_data(pointer).await?;
+                return Ok(Some(WithinItem { ip_net, info }));
+            }
+        }
+        Ok(None)
+    }
+
+    // The next network the options select, with the pointer to its record
+    // unless it has no data
+    async fn next_network(&mut self) -> Result<Option<(IpNetwork, Option<usize>)>, MaxMindDBError> {
+        while let Some(current) = self.stack.pop() {
+            let bit_count = current.ip_bytes.len() * 8;
+
+            // Skip networks that are aliases for the IPv4 network
+            if self.options.skip_aliased_networks && self.reader.is_ipv4_alias(current.node, &current.ip_bytes) {
+                continue;
+            }
+
+            match current.node.cmp(&self.node_count) {
+                Ordering::Greater => {
+                    // This is a data node, emit it and we're done (until the following next call)
+                    if !self.options.include_empty_networks && self.reader.is_empty_record(current.node).await? {
+                        continue;
+                    }
+                    let ip_net = bytes_and_prefix_to_net(&current.ip_bytes, current.prefix_len as u8)?;
+                    return Ok(Some((ip_net, Some(current.node))));
+                }
+                Ordering::Equal => {
+                    // Dead end, only emitted when networks without data are included
+                    if self.options.include_networks_without_data {
+                        let ip_net = bytes_and_prefix_to_net(&current.ip_bytes, current.prefix_len as u8)?;
+                        return Ok(Some((ip_net, None)));
+                    }
+                }
+                Ordering::Less => {
+                    // In order traversal of our children
+                    // right/1-bit
+                    let mut right_ip_bytes = current.ip_bytes.clone();
+                    right_ip_bytes[current.prefix_len >> 3] |= 1 << ((bit_count - current.prefix_len - 1) % 8);
+                    let node = self.reader.read_node(current.node, 1).await?;
+                    self.stack.push(WithinNode {
+                        node,
+                        ip_bytes: right_ip_bytes,
+                        prefix_len: current.prefix_len + 1,
+                    });
+                    // left/0-bit
+                    let node = self.reader.read_node(current.node, 0).await?;
+                    self.stack.push(WithinNode {
+                        node,
+                        ip_bytes: current.ip_bytes,
+                        prefix_len: current.prefix_len + 1,
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Walks all networks of the database, see [`Reader::networks`]. Networks
+/// without data have no record.
+pub struct Networks<'a, T: DeserializeOwned, S: ReadAt> {
+    inner: Within<'a, T, S>,
+}
+
+impl<'a, T: DeserializeOwned, S: ReadAt> Networks<'a, T, S> {
+    /// Returns the next network with its record, or `None` once the whole
+    /// tree has been visited.
+    pub async fn next(&mut self) -> Result<Option<WithinItem<Option<T>>>, MaxMindDBError> {
+        let (ip_net, pointer) = match self.inner.next_network().await? {
+            Some(network) => network,
+            None => return Ok(None),
+        };
+        let info = match pointer {
+            Some(pointer) => Some(self.inner.reader.decode_data(pointer).await?),
+            None => None,
+        };
+        Ok(Some(WithinItem { ip_net, info }))
+    }
 }
 
 /// A reader for the MaxMind DB format. The lifetime `'data` is tied to the lifetime of the underlying buffer holding the contents of the database file.
-pub struct Reader<S: AsyncRead + AsyncSeek + Unpin> {
+pub struct Reader<S: ReadAt> {
     source: Source<S>,
     pub metadata: Metadata,
     ipv4_start: usize,
     pointer_base: usize,
+    metadata_start: usize,
+    // Initial size of the buffers data is decoded from
+    buffer_size: usize,
+    // Records of the nodes loaded by `ReaderOptions::preload_levels`
+    preloaded_nodes: Arc<HashMap<usize, [usize; 2]>>,
+    // The whole search tree, with `ReaderOptions::load_search_tree`
+    search_tree: Option<Arc<Vec<u8>>>,
+    // The indexes built by `Reader::cached_index`, by field path and key type
+    indexes: Arc<Mutex<CachedIndexes>>,
 }
 
-impl Reader<File> {
-    pub async fn open_readfile(database: &str) -> Result<Reader<File>, MaxMindDBError> {
-        let source = Source::new(database).await?;
-        Ok(Reader::from_source(source).await?)
+// Type-erased `Arc<Index<K>>`s by field path and `TypeId` of `K`
+type CachedIndexes = HashMap<(String, TypeId), Arc<dyn Any + Send + Sync>>;
+
+/// Clones share the preloaded nodes and the cached indexes but read through
+/// their own buffers, so each task can use its own clone of a reader over a
+/// [`PreadFile`].
+impl<S: ReadAt + Clone> Clone for Reader<S> {
+    fn clone(&self) -> Reader<S> {
+        Reader {
+            source: self.source.clone(),
+            metadata: self.metadata.clone(),
+            ipv4_start: self.ipv4_start,
+            pointer_base: self.pointer_base,
+            metadata_start: self.metadata_start,
+            buffer_size: self.buffer_size,
+            preloaded_nodes: self.preloaded_nodes.clone(),
+            search_tree: self.search_tree.clone(),
+            indexes: self.indexes.clone(),
+        }
     }
 }
 
-impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
-    pub async fn from_source(mut source: Source<S>) -> Result<Reader<S>, MaxMindDBError> {
-        let data_section_separator_size = 16;
+#[cfg(feature = "tokio")]
+impl Reader<tokio::fs::File> {
+    pub async fn open_readfile(database: &str) -> Result<Reader<tokio::fs::File>, MaxMindDBError> {
+        ReaderOptions::new().open(database).await
+    }
 
-        let metadata_start = find_metadata_start(&mut source).await?;
-        source.move_cursor(metadata_start as u64).await?;
+    /// Like [`Reader::open_readfile`], but without validating the metadata,
+    /// see [`Reader::from_source_unchecked`].
+    pub async fn open_readfile_unchecked(database: &str) -> Result<Reader<tokio::fs::File>, MaxMindDBError> {
+        ReaderOptions::new().verify(false).open(database).await
+    }
+}
 
-        let metadata = try_decode_increasing_buffer(&mut source, 0, |buf| {
-            let mut type_decoder = decoder::Decoder::new(buf, 0);
-            Metadata::deserialize(&mut type_decoder).ok()    
-        })
-        .await?
-        .ok_or_else(|| MaxMindDBError::DecodingError("Couldn't decode Metadata".to_owned()))?;
+#[cfg(feature = "tokio")]
+impl Reader<PreadFile> {
+    /// Open a database read with positional reads, see [`PreadFile`].
+    pub async fn open_pread<P: AsRef<std::path::Path>>(database: P) -> Result<Reader<PreadFile>, MaxMindDBError> {
+        ReaderOptions::new().open_pread(database).await
+    }
+}
 
-        let search_tree_size = (metadata.node_count as usize) * (metadata.record_size as usize) / 4;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl Reader<UringFile> {
+    /// Open a database read through io_uring, see [`UringFile`].
+    pub async fn open_uring<P: AsRef<std::path::Path>>(database: P) -> Result<Reader<UringFile>, MaxMindDBError> {
+        ReaderOptions::new().open_uring(database).await
+    }
+}
 
-        let mut reader = Reader {
-            source,
-            pointer_base: search_tree_size + data_section_separator_size,
-            metadata,
-            ipv4_start: 0,
-        };
-        reader.ipv4_start = reader.find_ipv4_start().await?;
+#[cfg(feature = "http")]
+impl Reader<HttpFile> {
+    /// Open a database served over HTTP, see [`HttpFile`]. Reads go through a
+    /// 4 MiB block cache, use [`ReaderOptions::open_http`] for other sizes.
+    pub async fn open_http(url: &str) -> Result<Reader<HttpFile>, MaxMindDBError> {
+        ReaderOptions::new().cache_bytes(4 << 20).open_http(url).await
+    }
+}
 
-        Ok(reader)
+#[cfg(feature = "object-store")]
+impl Reader<ObjectStoreFile> {
+    /// Open a database in an object store, see [`ObjectStoreFile::open_url`].
+    /// The store is configured from the environment, and reads go through a
+    /// 4 MiB block cache, use [`ReaderOptions::open_object_store`] for other
+    /// settings.
+    pub async fn open_object_store(url: &str) -> Result<Reader<ObjectStoreFile>, MaxMindDBError> {
+        let file = ObjectStoreFile::open_url(url, std::env::vars()).await?;
+        ReaderOptions::new().cache_bytes(4 << 20).open_object_store(file).await
     }
+}
 
-    /// Lookup the socket address in the opened MaxMind DB
+#[cfg(feature = "mmap")]
+impl Reader<Cursor<memmap2::Mmap>> {
+    /// Open a database by memory mapping the file.
+    pub async fn open_mmap<P: AsRef<std::path::Path>>(
+        database: P,
+    ) -> Result<Reader<Cursor<memmap2::Mmap>>, MaxMindDBError> {
+        ReaderOptions::new().open_mmap(database).await
+    }
+}
+
+impl Reader<Cursor<Vec<u8>>> {
+    /// Open a database held in memory, e.g. one built with
+    /// [`writer::Writer`].
+    pub async fn from_bytes(buf: Vec<u8>) -> Result<Reader<Cursor<Vec<u8>>>, MaxMindDBError> {
+        Reader::from_source(Source::from_bytes(buf)).await
+    }
+
+    /// Open a compressed database, decompressing it into memory. See
+    /// [`compressed`] for the supported formats.
+    #[cfg(all(feature = "tokio", any(feature = "gzip", feature = "zstd")))]
+    pub async fn open_compressed<P: AsRef<std::path::Path>>(
+        database: P,
+    ) -> Result<Reader<Cursor<Vec<u8>>>, MaxMindDBError> {
+        let database = database.as_ref().to_owned();
+        let buf = tokio::task::spawn_blocking(move || compressed::read_file(database))
+            .await
+            .map_err(|e| MaxMindDBError::IoError(e.to_string()))??;
+        Reader::from_bytes(buf).await
+    }
+}
+
+impl<S: ReadAt> Reader<S> {
+    /// Opens a database, checking that its metadata describes a MaxMind DB
+    /// version 2 file that the search tree fits in.
+    pub async fn from_source(source: Source<S>) -> Result<Reader<S>, MaxMindDBError> {
+        ReaderOptions::new().open_source(source).await
+    }
+
+    /// Opens a database without validating its metadata, for experimental
+    /// formats. Invalid databases fail on lookup instead.
+    pub async fn from_source_unchecked(source: Source<S>) -> Result<Reader<S>, MaxMindDBError> {
+        ReaderOptions::new().verify(false).open_source(source).await
+    }
+
+    /// Decodes the whole metadata map into `T`, for databases with custom
+    /// metadata keys.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// #[derive(serde::Deserialize)]
+    /// struct BuildInfo {
+    ///     database_type: String,
+    ///     build_epoch: u64,
+    /// }
+    ///
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+    /// let info: BuildInfo = reader.metadata_as().await.unwrap();
+    /// assert_eq!(info.database_type, "GeoIP2-City");
+    /// # }
+    /// ```
+    pub async fn metadata_as<T>(&mut self) -> Result<T, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+    {
+        self.source.move_cursor(self.metadata_start as u64).await?;
+        try_decode_increasing_buffer(&mut self.source, 0, self.buffer_size, |buf| decode_buf(buf, 0))
+            .await?
+            .ok_or_else(|| MaxMindDBError::DecodingError("Couldn't decode Metadata".to_owned()))
+    }
+
+    /// The size of the data section in bytes, i.e. of everything between the
+    /// data section separator and the metadata.
+    pub fn data_section_size(&self) -> usize {
+        (self.metadata_start - METADATA_START_MARKER.len()).saturating_sub(self.pointer_base)
+    }
+
+    /// Lookup the socket address in the opened MaxMind DB. The address can be
+    /// an `IpAddr`, `Ipv4Addr`, `Ipv6Addr`, `SocketAddr`, a string or the
+    /// octets of an address, see [`IntoLookupAddr`]. IPv6 addresses other
+    /// than IPv4-mapped ones fail with `IpVersionMismatchError` in IPv4
+    /// databases.
     ///
     /// Example:
     ///
@@ -121,13 +555,18 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
     /// use std::net::IpAddr;
     /// use std::str::FromStr;
     ///
-    /// let reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
     ///
     /// let ip: IpAddr = FromStr::from_str("89.160.20.128").unwrap();
-    /// let city: geoip2::City = reader.lookup(ip).unwrap();
+    /// let city: geoip2::City = reader.lookup(ip).await.unwrap();
     /// print!("{:?}", city);
+    ///
+    /// let city: geoip2::City = reader.lookup("89.160.20.128").await.unwrap();
+    /// # }
     /// ```
-    pub async fn lookup<T>(&mut self, address: IpAddr) -> Result<T, MaxMindDBError>
+    pub async fn lookup<T>(&mut self, address: impl IntoLookupAddr) -> Result<T, MaxMindDBError>
     where
         T: DeserializeOwned,
     {
@@ -143,36 +582,325 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
     /// use std::net::IpAddr;
     /// use std::str::FromStr;
     ///
-    /// let reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
     ///
     /// let ip: IpAddr = "89.160.20.128".parse().unwrap();
-    /// let (city, prefix_len) = reader.lookup_prefix::<geoip2::City>(ip).unwrap();
+    /// let (city, prefix_len) = reader.lookup_prefix::<geoip2::City>(ip).await.unwrap();
     /// print!("{:?}, prefix length: {}", city, prefix_len);
+    /// # }
     /// ```
-    pub async fn lookup_prefix<T>(&mut self, address: IpAddr) -> Result<(T, usize), MaxMindDBError>
+    pub async fn lookup_prefix<T>(&mut self, address: impl IntoLookupAddr) -> Result<(T, usize), MaxMindDBError>
     where
         T: DeserializeOwned,
     {
+        let (address, mapped) = unmap_ipv4(address.into_lookup_addr()?);
+        self.check_ip_version(address)?;
         let ip_bytes = ip_to_bytes(address);
-        let (pointer, prefix_len) = self.find_address_in_tree(&ip_bytes).await?;
+        let (pointer, mut prefix_len) = self.find_address_in_tree(&ip_bytes).await?;
+        if mapped && self.metadata.ip_version == 6 {
+            prefix_len += 96;
+        }
         if pointer == 0 {
             return Err(MaxMindDBError::AddressNotFoundError(
                 "Address not found in database".to_owned(),
             ));
         }
 
-        let rec = self.resolve_data_pointer(pointer)?;
-        self.source.move_cursor(self.pointer_base as u64).await?;
+        self.decode_data(pointer).await.map(|v| (v, prefix_len))
+    }
+
+    /// Iterate over all networks contained in `cidr` together with their records
+    ///
+    /// Unlike the [`NetworksOptions`] defaults, the IPv4 aliases of IPv6
+    /// databases are skipped, so each IPv4 network is visited once, and
+    /// networks whose record is an empty map or array are kept, since they
+    /// are part of `cidr` too.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use ipnetwork::IpNetwork;
+    /// use maxminddb::geoip2;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+    ///
+    /// let ip_net: IpNetwork = "89.160.20.0/24".parse().unwrap();
+    /// let mut iter = reader.within::<geoip2::City>(ip_net).await.unwrap();
+    /// while let Some(item) = iter.next().await.unwrap() {
+    ///     println!("{}: {:?}", item.ip_net, item.info.city);
+    /// }
+    /// # }
+    /// ```
+    pub async fn within<T>(&mut self, cidr: IpNetwork) -> Result<Within<'_, T, S>, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+    {
+        let options = NetworksOptions::new()
+            .skip_aliased_networks(true)
+            .include_empty_networks(true);
+        self.walk(cidr, options).await
+    }
+
+    /// Iterate over all networks of the database. Which networks are visited
+    /// is chosen with `options`, see [`NetworksOptions`].
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use maxminddb::{geoip2, NetworksOptions};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+    ///
+    /// let options = NetworksOptions::new().skip_aliased_networks(true);
+    /// let mut iter = reader.networks::<geoip2::City>(options).await.unwrap();
+    /// while let Some(item) = iter.next().await.unwrap() {
+    ///     println!("{}: {:?}", item.ip_net, item.info.and_then(|city| city.city));
+    /// }
+    /// # }
+    /// ```
+    pub async fn networks<T>(&mut self, options: NetworksOptions) -> Result<Networks<'_, T, S>, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+    {
+        let network = match self.metadata.ip_version {
+            6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+        let cidr = IpNetwork::new(network, 0).map_err(|e| MaxMindDBError::InvalidNetworkError(e.to_string()))?;
+        let inner = self.walk(cidr, options).await?;
+        Ok(Networks { inner })
+    }
+
+    /// Finds the networks whose record matches `predicate`, e.g. all networks
+    /// of a country. Networks sharing a record share its offset in the data
+    /// section: the tree is walked first to collect the offsets of the
+    /// networks, then each distinct record is decoded and tested once, in the
+    /// order of the data section, and the networks pointing to the matching
+    /// ones are returned. The networks are returned in the order of the
+    /// tree, and the IPv4 aliases of IPv6 databases are skipped.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use maxminddb::geoip2;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+    ///
+    /// let networks = reader
+    ///     .find_networks(|city: &geoip2::City| {
+    ///         city.country.as_ref().and_then(|country| country.iso_code.as_deref()) == Some("SE")
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn find_networks<T, F>(&mut self, mut predicate: F) -> Result<Vec<IpNetwork>, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+        F: FnMut(&T) -> bool,
+    {
+        let options = NetworksOptions::new()
+            .skip_aliased_networks(true)
+            .include_empty_networks(true);
+        let mut iter = self.networks::<T>(options).await?;
+        let mut networks = Vec::new();
+        while let Some((ip_net, pointer)) = iter.inner.next_network().await? {
+            if let Some(pointer) = pointer {
+                networks.push((ip_net, pointer));
+            }
+        }
+
+        let mut pointers: Vec<usize> = networks.iter().map(|&(_, pointer)| pointer).collect();
+        pointers.sort_unstable();
+        pointers.dedup();
+        let mut matches = HashSet::new();
+        for pointer in pointers {
+            let record: T = self.decode_data(pointer).await?;
+            if predicate(&record) {
+                matches.insert(pointer);
+            }
+        }
+        Ok(networks
+            .into_iter()
+            .filter(|(_, pointer)| matches.contains(pointer))
+            .map(|(ip_net, _)| ip_net)
+            .collect())
+    }
+
+    async fn walk<T>(&mut self, cidr: IpNetwork, options: NetworksOptions) -> Result<Within<'_, T, S>, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+    {
+        self.check_ip_version(cidr.network())?;
+        let mut ip_bytes = ip_to_bytes(cidr.network());
+        let bit_count = ip_bytes.len() * 8;
+
+        let mut node = self.start_node(bit_count);
+        let node_count = self.metadata.node_count as usize;
+
+        let mut stack: Vec<WithinNode> = Vec::with_capacity(bit_count - cidr.prefix() as usize);
+
+        // Traverse down the tree to the level that matches the cidr mark
+        let mut prefix_len = 0_usize;
+        while prefix_len < cidr.prefix() as usize && node < node_count {
+            let bit = 1 & (ip_bytes[prefix_len >> 3] >> (7 - (prefix_len % 8))) as usize;
+            node = self.read_node(node, bit).await?;
+            prefix_len += 1;
+        }
+        // If we hit a record before exhausting the cidr mask, the whole cidr
+        // is covered by that record's network, which is then the only one
+        // "within"
+        mask_ip_bytes(&mut ip_bytes, prefix_len);
+
+        // Anything that's below node in the tree is "within", start with the
+        // node we traversed to as our to be processed stack. An empty record
+        // leaves the stack empty and the iterator visits nothing, unless
+        // networks without data are visited.
+        if node != node_count || options.include_networks_without_data {
+            stack.push(WithinNode {
+                node,
+                ip_bytes,
+                prefix_len,
+            });
+        }
 
-        try_decode_increasing_buffer(&mut self.source, rec, |buf| {
-            let mut decoder = decoder::Decoder::new(buf, rec);
-            T::deserialize(&mut decoder).map(|v| (v, prefix_len)).map_err(|e| dbg!(e)).ok()
+        Ok(Within {
+            reader: self,
+            node_count,
+            stack,
+            options,
+            phantom: PhantomData,
         })
-        .await?
-        .ok_or_else(|| MaxMindDBError::DecodingError(format!("Error decoding {}", std::any::type_name::<T>())))
+    }
+
+    async fn decode_data<T>(&mut self, pointer: usize) -> Result<T, MaxMindDBError>
+    where
+        T: DeserializeOwned,
+    {
+        let rec = self.resolve_data_pointer(pointer)?;
+        self.decode_at(rec, |decoder| T::deserialize(decoder))
+            .await?
+            .ok_or_else(|| {
+                MaxMindDBError::InvalidDatabaseError(format!(
+                    "unexpected end of data decoding {}",
+                    std::any::type_name::<T>()
+                ))
+            })
+    }
+
+    // Decodes with `f` from `offset` of the data section. The data is read
+    // in windows: `buffer_size` bytes at `offset` first, then, each time `f`
+    // runs past them, a larger window or one at the pointer target it
+    // missed. Returns `None` if the data section ends before `f` succeeds.
+    async fn decode_at<F, O>(&mut self, offset: usize, mut f: F) -> Result<Option<O>, MaxMindDBError>
+    where
+        F: FnMut(&mut decoder::Decoder<'_>) -> Result<O, MaxMindDBError>,
+    {
+        let data_size = self.data_section_size();
+        let mut windows: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut missing = (offset, 0);
+        loop {
+            let (start, size) = missing;
+            if start + size > data_size {
+                return Ok(None);
+            }
+            let grown = windows.iter().position(|(at, window)| *at <= start && start <= at + window.len());
+            let (at, len) = match grown {
+                Some(i) => (windows[i].0, (2 * windows[i].1.len()).max(start + size - windows[i].0)),
+                None => (start, self.buffer_size.max(size)),
+            };
+            let len = len.min(data_size - at);
+            let window = self.source.read_at((self.pointer_base + at) as u64, len).await?.to_vec();
+            match grown {
+                Some(i) => windows[i].1 = window,
+                None => windows.push((at, window)),
+            }
+
+            let parts = windows.iter().map(|(at, window)| (*at, &window[..])).collect();
+            let mut decoder = decoder::Decoder::with_windows(parts, offset);
+            match f(&mut decoder) {
+                Ok(out) => return Ok(Some(out)),
+                Err(e) => match decoder.missing() {
+                    Some(next) => missing = next,
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+
+    /// The left (0 bit) and right (1 bit) records of node `node` of the
+    /// search tree, the root being node 0. For debugging and tooling that
+    /// work with the layout of the tree, lookups don't need it.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use maxminddb::Record;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+    ///
+    /// let (left, right) = reader.node(0).await.unwrap();
+    /// if let Record::Node(next) = left {
+    ///     println!("0 leads to node {}", next);
+    /// }
+    /// # }
+    /// ```
+    pub async fn node(&mut self, node: usize) -> Result<(Record, Record), MaxMindDBError> {
+        if node >= self.metadata.node_count as usize {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "node {} is outside the search tree of {} nodes",
+                node, self.metadata.node_count
+            )));
+        }
+        let left = self.read_node(node, 0).await?;
+        let right = self.read_node(node, 1).await?;
+        Ok((self.to_record(left)?, self.to_record(right)?))
+    }
+
+    /// The nodes a lookup of `address` goes through, in order. IPv4
+    /// addresses in IPv6 databases start at the root of the IPv4 subtree
+    /// rather than node 0, as lookups do.
+    pub async fn path_for(&mut self, address: impl IntoLookupAddr) -> Result<Vec<usize>, MaxMindDBError> {
+        let (address, _) = unmap_ipv4(address.into_lookup_addr()?);
+        self.check_ip_version(address)?;
+        let mut path = Vec::new();
+        self.walk_tree(&ip_to_bytes(address), |node| path.push(node)).await?;
+        Ok(path)
+    }
+
+    fn to_record(&self, value: usize) -> Result<Record, MaxMindDBError> {
+        let node_count = self.metadata.node_count as usize;
+        match value {
+            n if n < node_count => Ok(Record::Node(n)),
+            n if n == node_count => Ok(Record::Empty),
+            // Past the 16 byte data section separator
+            n if n >= node_count + 16 => self.resolve_data_pointer(n).map(Record::Data),
+            _ => Err(MaxMindDBError::InvalidDatabaseError(
+                "invalid node in search tree".to_owned(),
+            )),
+        }
     }
 
     async fn find_address_in_tree(&mut self, ip_address: &[u8]) -> Result<(usize, usize), MaxMindDBError> {
+        self.walk_tree(ip_address, |_| {}).await
+    }
+
+    // Follows the bits of `ip_address` down the tree, calling `visit` with
+    // each node on the way
+    async fn walk_tree<F>(&mut self, ip_address: &[u8], mut visit: F) -> Result<(usize, usize), MaxMindDBError>
+    where
+        F: FnMut(usize),
+    {
         let bit_count = ip_address.len() * 8;
         let mut node = self.start_node(bit_count);
 
@@ -184,6 +912,7 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
                 prefix_len = i;
                 break;
             }
+            visit(node);
             let bit = 1 & (ip_address[i >> 3] >> (7 - (i % 8)));
 
             node = self.read_node(node, bit as usize).await?;
@@ -197,6 +926,18 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
         }
     }
 
+    // IPv6 addresses can't be looked up in the 32 bit deep tree of an IPv4
+    // database
+    fn check_ip_version(&self, address: IpAddr) -> Result<(), MaxMindDBError> {
+        if address.is_ipv6() && self.metadata.ip_version == 4 {
+            return Err(MaxMindDBError::IpVersionMismatchError(format!(
+                "IPv6 address {} in an IPv4-only database",
+                address
+            )));
+        }
+        Ok(())
+    }
+
     fn start_node(&self, length: usize) -> usize {
         if length == 128 {
             0
@@ -222,42 +963,73 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
         Ok(node)
     }
 
-    async fn read_node(&mut self, node_number: usize, index: usize) -> Result<usize, MaxMindDBError> {
-        let base_offset = node_number * (self.metadata.record_size as usize) / 4;
+    // Whether the record at `pointer` is an empty map or array
+    async fn is_empty_record(&mut self, pointer: usize) -> Result<bool, MaxMindDBError> {
+        let rec = self.resolve_data_pointer(pointer)?;
+        self.decode_at(rec, |decoder| decoder.is_empty_value())
+            .await?
+            .ok_or_else(|| MaxMindDBError::InvalidDatabaseError("unexpected end of data decoding a record".to_owned()))
+    }
 
-        let val = match self.metadata.record_size {
-            24 => {
-                let offset = base_offset + index * 3;
-                to_usize(0, self.source.read_at(offset as u64, 3).await?) // &buf[offset..offset + 3]
-            }
-            28 => {
-                let mut middle = self.source.read_one(base_offset as u64 + 3).await?;
-                if index != 0 {
-                    middle &= 0x0F
-                } else {
-                    middle = (0xF0 & middle) >> 4
+    // The IPv4 subtree of an IPv6 database is also reachable through aliases
+    // such as ::ffff:0:0/96, detect when a walk reaches it that way.
+    fn is_ipv4_alias(&self, node: usize, ip_bytes: &[u8]) -> bool {
+        self.ipv4_start != 0
+            && node == self.ipv4_start
+            && ip_bytes.len() == 16
+            && ip_bytes[..12].iter().any(|&b| b != 0)
+    }
+
+    // Loads the records of the first `levels` levels of the search tree
+    async fn preload_nodes(&mut self, levels: usize) -> Result<(), MaxMindDBError> {
+        let node_count = self.metadata.node_count as usize;
+        let mut nodes = HashMap::new();
+        let mut level = vec![0];
+        for _ in 0..levels {
+            let mut next = Vec::with_capacity(level.len() * 2);
+            for node in level {
+                if node >= node_count || nodes.contains_key(&node) {
+                    continue;
                 }
-                let offset = base_offset + index * 4;
-                to_usize(middle, self.source.read_at(offset as u64, 3).await?) //&buf[offset..offset + 3])
-            }
-            32 => {
-                let offset = base_offset + index * 4;
-                to_usize(0, self.source.read_at(offset as u64, 4).await?) // &buf[offset..offset + 4])
+                let records = [self.read_node(node, 0).await?, self.read_node(node, 1).await?];
+                next.extend_from_slice(&records);
+                nodes.insert(node, records);
             }
-            s => {
-                return Err(MaxMindDBError::InvalidDatabaseError(format!(
-                    "unknown record size: \
-                     {:?}",
-                    s
-                )))
+            level = next;
+        }
+        self.preloaded_nodes = Arc::new(nodes);
+        Ok(())
+    }
+
+    async fn read_node(&mut self, node_number: usize, index: usize) -> Result<usize, MaxMindDBError> {
+        if let Some(records) = self.preloaded_nodes.get(&node_number) {
+            return Ok(records[index]);
+        }
+        let record_size = self.metadata.record_size;
+        let node_size = record_size as usize / 4;
+        let base_offset = node_number * node_size;
+
+        match &self.search_tree {
+            Some(tree) => match tree.get(base_offset..base_offset + node_size) {
+                Some(node) => read_record(node, record_size, index),
+                None => Err(MaxMindDBError::InvalidDatabaseError(format!(
+                    "node {} is outside the search tree",
+                    node_number
+                ))),
+            },
+            None => {
+                let node = self.source.read_at(base_offset as u64, node_size).await?;
+                read_record(node, record_size, index)
             }
-        };
-        Ok(val)
+        }
     }
 
     fn resolve_data_pointer(&self, pointer: usize) -> Result<usize, MaxMindDBError> {
-        let resolved = pointer - (self.metadata.node_count as usize) - 16;
-        
+        // Pointers below the data section separator don't point to data
+        let resolved = pointer
+            .checked_sub(self.metadata.node_count as usize + 16)
+            .ok_or_else(|| MaxMindDBError::InvalidDatabaseError("invalid node in search tree".to_owned()))?;
+
         if resolved > self.source.total_size {
             return Err(MaxMindDBError::InvalidDatabaseError(
                 "the MaxMind DB file's search tree \
@@ -270,8 +1042,38 @@ impl<'de, S: AsyncRead + AsyncSeek + Unpin> Reader<S> {
     }
 }
 
-// I haven't moved all patterns of this form to a generic function as
-// the FromPrimitive trait is unstable
+// Reads the left (`index` 0) or right (`index` 1) record of a node
+fn read_record(node: &[u8], record_size: u16, index: usize) -> Result<usize, MaxMindDBError> {
+    let val = match record_size {
+        24 => {
+            let offset = index * 3;
+            to_usize(0, &node[offset..offset + 3])
+        }
+        28 => {
+            let mut middle = node[3];
+            if index != 0 {
+                middle &= 0x0F
+            } else {
+                middle = (0xF0 & middle) >> 4
+            }
+            let offset = index * 4;
+            to_usize(middle, &node[offset..offset + 3])
+        }
+        32 => {
+            let offset = index * 4;
+            to_usize(0, &node[offset..offset + 4])
+        }
+        s => {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "unknown record size: \
+                 {:?}",
+                s
+            )))
+        }
+    };
+    Ok(val)
+}
+
 fn to_usize(base: u8, bytes: &[u8]) -> usize {
     bytes
         .iter()
@@ -285,44 +1087,127 @@ fn ip_to_bytes(address: IpAddr) -> Vec<u8> {
     }
 }
 
-async fn find_metadata_start<S: AsyncRead + AsyncSeek + Unpin>(source: &mut Source<S>) -> Result<usize, MaxMindDBError> {
-    const METADATA_START_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+fn mask_ip_bytes(bytes: &mut [u8], prefix_len: usize) {
+    for (i, b) in bytes.iter_mut().enumerate() {
+        let keep = prefix_len.saturating_sub(i * 8).min(8);
+        *b &= !(0xFF_u16 >> keep) as u8;
+    }
+}
 
-    try_decode_increasing_buffer(source, 0, |buf| 
-        memchr::memmem::rfind(buf, METADATA_START_MARKER)
-        .map(|idx| idx + METADATA_START_MARKER.len()))
-    .await?
-    .ok_or_else(|| MaxMindDBError::InvalidDatabaseError(
-        "Could not find MaxMind DB metadata in file.".to_owned(),
-    ))
+fn bytes_and_prefix_to_net(bytes: &[u8], prefix: u8) -> Result<IpNetwork, MaxMindDBError> {
+    let (ip, pre) = match bytes.len() {
+        4 => (IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])), prefix),
+        16 => {
+            if prefix >= 96 && bytes[..12].iter().all(|&b| b == 0) {
+                // It's actually v4, but in v6 form, convert would be nice if ipnetwork had this
+                // logic.
+                (
+                    IpAddr::V4(Ipv4Addr::new(bytes[12], bytes[13], bytes[14], bytes[15])),
+                    prefix - 96,
+                )
+            } else {
+                let mut octets = [0_u8; 16];
+                octets.copy_from_slice(bytes);
+                (IpAddr::V6(Ipv6Addr::from(octets)), prefix)
+            }
+        }
+        // This should never happen
+        _ => return Err(MaxMindDBError::InvalidNetworkError("invalid address".to_owned())),
+    };
+    IpNetwork::new(ip, pre).map_err(|e| MaxMindDBError::InvalidNetworkError(e.to_string()))
+}
+
+// Looks up IPv4-mapped IPv6 addresses as the IPv4 address they map. Returns
+// the address to look up and whether it was mapped.
+fn unmap_ipv4(address: IpAddr) -> (IpAddr, bool) {
+    match address {
+        IpAddr::V6(a) => a.to_ipv4_mapped().map_or((address, false), |a| (IpAddr::V4(a), true)),
+        IpAddr::V4(_) => (address, false),
+    }
+}
+
+const METADATA_START_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+// The metadata section is at most 128KiB, including the marker
+const METADATA_MAX_SIZE: usize = 128 * 1024;
+
+async fn find_metadata_start<S: ReadAt>(source: &mut Source<S>) -> Result<usize, MaxMindDBError> {
+    let size = source.total_size.min(METADATA_MAX_SIZE);
+    let start = source.total_size - size;
+    let buf = source.read_at(start as u64, size).await?;
+    find_metadata_start_in(buf, start)
+}
+
+// Finds the start of the metadata in `buf`, the end of a database from
+// offset `base` on. Only the last `METADATA_MAX_SIZE` bytes are searched.
+fn find_metadata_start_in(buf: &[u8], base: usize) -> Result<usize, MaxMindDBError> {
+    let search_start = buf.len().saturating_sub(METADATA_MAX_SIZE);
+    memchr::memmem::rfind(&buf[search_start..], METADATA_START_MARKER)
+        .map(|idx| base + search_start + idx + METADATA_START_MARKER.len())
+        .ok_or_else(|| MaxMindDBError::InvalidDatabaseError(
+            "Could not find MaxMind DB metadata in file.".to_owned(),
+        ))
+}
+
+// Decodes a `T` at `start` of `buf`, or returns `None` if `buf` ends before
+// the value does.
+fn decode_buf<T: DeserializeOwned>(buf: &[u8], start: usize) -> Result<Option<T>, MaxMindDBError> {
+    let mut decoder = decoder::Decoder::new(buf, start);
+    match T::deserialize(&mut decoder) {
+        Ok(value) => Ok(Some(value)),
+        Err(_) if decoder.is_truncated() => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
-async fn try_decode_increasing_buffer<S, F, O>(source: &mut Source<S>, rec: usize, f: F) -> Result<Option<O>, MaxMindDBError> 
+// Calls `f` with the data from the cursor on, `rec` plus `base` bytes at
+// first and `base` more bytes each time `f` returns `None`. Returns `None`
+// if the data ends before `f` succeeds.
+async fn try_decode_increasing_buffer<S, F, O>(
+    source: &mut Source<S>,
+    rec: usize,
+    base: usize,
+    mut f: F,
+) -> Result<Option<O>, MaxMindDBError>
 where
-    S: AsyncRead + AsyncSeek + Unpin,
-    F: Fn(&[u8]) -> Option<O>,
+    S: ReadAt,
+    F: FnMut(&[u8]) -> Result<Option<O>, MaxMindDBError>,
 {
-    const BASE: usize = 1024;
     let start_position = source.position().await?;
-    let max_size = source.total_size - start_position as usize;
+    let max_size = source.total_size.saturating_sub(start_position as usize);
 
     for size_mult in 1..usize::MAX {
         source.move_cursor(start_position).await?;
-        if rec + size_mult * BASE > max_size {
+        if rec + size_mult * base >= max_size {
             let buf = source.read(max_size).await?;
-            return Ok(f(buf))
+            return f(buf);
         }
-        let buf = source.read(rec + size_mult * BASE).await?;
-        if let Some(out) = f(buf) {
-            return Ok(Some(out))
+        let buf = source.read(rec + size_mult * base).await?;
+        if let Some(out) = f(buf)? {
+            return Ok(Some(out));
         }
     }
     Ok(None)
 }
 
 mod decoder;
+mod options;
 mod source;
+mod value;
+pub mod blocking;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compressed;
+pub mod diff;
+#[cfg(feature = "export")]
+pub mod export;
 pub mod geoip2;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod index;
+pub mod stats;
+#[cfg(feature = "updater")]
+pub mod updater;
+pub mod writer;
 
 #[cfg(test)]
 mod reader_test;
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::source::{ReadAt, Source};
+use super::{
+    decoder, find_metadata_start, try_decode_increasing_buffer, MaxMindDBError, Metadata, Reader,
+    METADATA_START_MARKER,
+};
+
+/// Options for opening a [`Reader`].
+///
+/// Example:
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() {
+/// let reader = maxminddb::ReaderOptions::new()
+///     .cache_bytes(1 << 20)
+///     .preload_levels(16)
+///     .open("test-data/test-data/GeoIP2-City-Test.mmdb")
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReaderOptions {
+    cache_bytes: usize,
+    preload_levels: usize,
+    verify: bool,
+    buffer_size: usize,
+    load_search_tree: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            cache_bytes: 0,
+            preload_levels: 0,
+            verify: true,
+            buffer_size: 1024,
+            load_search_tree: false,
+        }
+    }
+}
+
+impl ReaderOptions {
+    pub fn new() -> ReaderOptions {
+        ReaderOptions::default()
+    }
+
+    /// Keeps up to `cache_bytes` of recently read blocks of the file in
+    /// memory. Disabled by default.
+    pub fn cache_bytes(mut self, cache_bytes: usize) -> ReaderOptions {
+        self.cache_bytes = cache_bytes;
+        self
+    }
+
+    /// Loads the nodes of the first `levels` levels of the search tree when
+    /// opening the database, so lookups don't read them from the file. A
+    /// level holds up to twice as many nodes as the one above it.
+    pub fn preload_levels(mut self, levels: usize) -> ReaderOptions {
+        self.preload_levels = levels;
+        self
+    }
+
+    /// Reads the whole search tree into memory when opening the database, so
+    /// only records are read from the file on lookups. The tree takes
+    /// `node_count * record_size / 4` bytes, see
+    /// [`Metadata::search_tree_size`](crate::Metadata::search_tree_size).
+    pub fn load_search_tree(mut self, load_search_tree: bool) -> ReaderOptions {
+        self.load_search_tree = load_search_tree;
+        self
+    }
+
+    /// Whether to validate the metadata when opening the database, see
+    /// [`Reader::from_source`]. Enabled by default.
+    pub fn verify(mut self, verify: bool) -> ReaderOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Sets how many bytes are read at a time to decode a record, defaults to
+    /// 1024. Reads start at the record, records that don't fit are read again
+    /// with a larger buffer and the data their pointers point to is read
+    /// separately.
+    pub fn buffer_size(mut self, buffer_size: usize) -> ReaderOptions {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn open(&self, database: &str) -> Result<Reader<tokio::fs::File>, MaxMindDBError> {
+        let source = Source::new(database).await?;
+        self.open_source(source).await
+    }
+
+    /// Opens a database read with positional reads, see
+    /// [`PreadFile`](crate::PreadFile).
+    #[cfg(feature = "tokio")]
+    pub async fn open_pread<P: AsRef<std::path::Path>>(
+        &self,
+        database: P,
+    ) -> Result<Reader<crate::PreadFile>, MaxMindDBError> {
+        let source = crate::PreadFile::open(database).await?.into_source()?;
+        self.open_source(source).await
+    }
+
+    /// Opens a database read through io_uring, see
+    /// [`UringFile`](crate::UringFile).
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub async fn open_uring<P: AsRef<std::path::Path>>(
+        &self,
+        database: P,
+    ) -> Result<Reader<crate::UringFile>, MaxMindDBError> {
+        let source = crate::UringFile::open(database).await?.into_source();
+        self.open_source(source).await
+    }
+
+    /// Opens a database served over HTTP, see [`HttpFile`](crate::HttpFile).
+    /// Without [`cache_bytes`](ReaderOptions::cache_bytes) every read of a
+    /// lookup is a request.
+    #[cfg(feature = "http")]
+    pub async fn open_http(&self, url: &str) -> Result<Reader<crate::HttpFile>, MaxMindDBError> {
+        let source = crate::HttpFile::open(url).await?.into_source();
+        self.open_source(source).await
+    }
+
+    /// Opens a database in an object store, see
+    /// [`ObjectStoreFile`](crate::ObjectStoreFile).
+    #[cfg(feature = "object-store")]
+    pub async fn open_object_store(
+        &self,
+        file: crate::ObjectStoreFile,
+    ) -> Result<Reader<crate::ObjectStoreFile>, MaxMindDBError> {
+        self.open_source(file.into_source()).await
+    }
+
+    /// Opens a database by memory mapping the file.
+    #[cfg(feature = "mmap")]
+    pub async fn open_mmap<P: AsRef<std::path::Path>>(
+        &self,
+        database: P,
+    ) -> Result<Reader<std::io::Cursor<memmap2::Mmap>>, MaxMindDBError> {
+        let file = std::fs::File::open(database)?;
+        // SAFETY: the database file must not be modified while it's mapped.
+        let mmap = unsafe { memmap2::Mmap::map(&file) }?;
+        self.open_source(Source::from_bytes(mmap)).await
+    }
+
+    pub async fn open_source<S>(&self, mut source: Source<S>) -> Result<Reader<S>, MaxMindDBError>
+    where
+        S: ReadAt,
+    {
+        let data_section_separator_size = 16;
+
+        source.set_cache_bytes(self.cache_bytes);
+        let metadata_start = find_metadata_start(&mut source).await?;
+        source.move_cursor(metadata_start as u64).await?;
+
+        let metadata = try_decode_increasing_buffer(&mut source, 0, self.buffer_size, |buf| {
+            let mut type_decoder = decoder::Decoder::new(buf, 0);
+            match Metadata::deserialize(&mut type_decoder) {
+                Ok(metadata) => Ok(Some(metadata)),
+                Err(_) if type_decoder.is_truncated() => Ok(None),
+                Err(e) => Err(MaxMindDBError::DecodingError(format!("Couldn't decode Metadata: {}", e))),
+            }
+        })
+        .await?
+        .ok_or_else(|| MaxMindDBError::DecodingError("Couldn't decode Metadata".to_owned()))?;
+        if self.verify {
+            metadata.validate(metadata_start - METADATA_START_MARKER.len())?;
+        }
+
+        let mut reader = Reader {
+            source,
+            pointer_base: metadata.search_tree_size() + data_section_separator_size,
+            metadata,
+            ipv4_start: 0,
+            metadata_start,
+            buffer_size: self.buffer_size,
+            preloaded_nodes: Arc::new(HashMap::new()),
+            search_tree: None,
+            indexes: Default::default(),
+        };
+        if self.load_search_tree {
+            let size = reader.metadata.search_tree_size();
+            let tree = reader.source.read_at(0, size).await?.to_vec();
+            reader.search_tree = Some(Arc::new(tree));
+        }
+        reader.preload_nodes(self.preload_levels).await?;
+        reader.ipv4_start = reader.find_ipv4_start().await?;
+
+        Ok(reader)
+    }
+}
+
+/// Options for walking all networks of a database with
+/// [`Reader::networks`]. The defaults match the libmaxminddb and Go readers:
+/// aliased networks are visited, networks whose record is an empty map or
+/// array and networks without data are skipped.
+///
+/// Example:
+///
+/// ```
+/// let options = maxminddb::NetworksOptions::new()
+///     .skip_aliased_networks(true)
+///     .include_networks_without_data(true);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworksOptions {
+    pub(crate) skip_aliased_networks: bool,
+    pub(crate) include_empty_networks: bool,
+    pub(crate) include_networks_without_data: bool,
+}
+
+impl NetworksOptions {
+    pub fn new() -> NetworksOptions {
+        NetworksOptions::default()
+    }
+
+    /// Skips the networks of IPv6 databases that alias the IPv4 networks,
+    /// e.g. `::ffff:0:0/96` and `2002::/16`, so every IPv4 network is only
+    /// visited once.
+    pub fn skip_aliased_networks(mut self, skip: bool) -> NetworksOptions {
+        self.skip_aliased_networks = skip;
+        self
+    }
+
+    /// Also visits networks whose record is an empty map or array.
+    pub fn include_empty_networks(mut self, include: bool) -> NetworksOptions {
+        self.include_empty_networks = include;
+        self
+    }
+
+    /// Also visits the networks that have no record, their record is `None`.
+    pub fn include_networks_without_data(mut self, include: bool) -> NetworksOptions {
+        self.include_networks_without_data = include;
+        self
+    }
+}
@@ -1,13 +1,16 @@
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
 
+use ipnetwork::IpNetwork;
 use serde::Deserialize;
-use tokio::io::{AsyncRead, AsyncSeek};
 
-use super::{MaxMindDBError, Reader};
+use super::{IpVersion, MaxMindDBError, ReadAt, Reader, Value};
 
 #[allow(clippy::float_cmp)]
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_decoder() {
     let _ = env_logger::try_init();
 
@@ -80,6 +83,7 @@ async fn test_decoder() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_pointers_in_metadata() {
     let _ = env_logger::try_init();
 
@@ -90,6 +94,7 @@ async fn test_pointers_in_metadata() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_broken_database() {
     let _ = env_logger::try_init();
 
@@ -111,6 +116,7 @@ async fn test_broken_database() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_missing_database() {
     let _ = env_logger::try_init();
 
@@ -126,6 +132,7 @@ async fn test_missing_database() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_non_database() {
     let _ = env_logger::try_init();
 
@@ -144,6 +151,7 @@ async fn test_non_database() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_reader() {
     let _ = env_logger::try_init();
 
@@ -165,6 +173,7 @@ async fn test_reader() {
 
 /// Create Reader by explicitly reading the entire file into a buffer.
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_reader_readfile() {
     let _ = env_logger::try_init();
 
@@ -186,7 +195,7 @@ async fn test_reader_readfile() {
 
 #[tokio::test]
 #[cfg(feature = "mmap")]
-fn test_reader_mmap() {
+async fn test_reader_mmap() {
     let _ = env_logger::try_init();
 
     let sizes = [24usize, 28, 32];
@@ -197,15 +206,16 @@ fn test_reader_mmap() {
                 "test-data/test-data/MaxMind-DB-test-ipv{}-{}.mmdb",
                 ip_version, record_size
             );
-            let mut reader = Reader::open_mmap(filename).ok().unwrap();
+            let mut reader = Reader::open_mmap(filename).await.ok().unwrap();
 
             check_metadata(&reader, *ip_version, *record_size);
-            check_ip(&reader, *ip_version);
+            check_ip(&mut reader, *ip_version).await;
         }
     }
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_city() {
     use super::geoip2::City;
     let _ = env_logger::try_init();
@@ -223,6 +233,7 @@ async fn test_lookup_city() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_country() {
     use super::geoip2::Country;
     let _ = env_logger::try_init();
@@ -240,6 +251,7 @@ async fn test_lookup_country() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_connection_type() {
     use super::geoip2::ConnectionType;
     let _ = env_logger::try_init();
@@ -255,6 +267,7 @@ async fn test_lookup_connection_type() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_annonymous_ip() {
     use super::geoip2::AnonymousIp;
     let _ = env_logger::try_init();
@@ -274,6 +287,7 @@ async fn test_lookup_annonymous_ip() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_density_income() {
     use super::geoip2::DensityIncome;
     let _ = env_logger::try_init();
@@ -290,6 +304,7 @@ async fn test_lookup_density_income() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_domain() {
     use super::geoip2::Domain;
     let _ = env_logger::try_init();
@@ -305,6 +320,7 @@ async fn test_lookup_domain() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_isp() {
     use super::geoip2::Isp;
     let _ = env_logger::try_init();
@@ -322,6 +338,7 @@ async fn test_lookup_isp() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_asn() {
     use super::geoip2::Asn;
     let _ = env_logger::try_init();
@@ -338,6 +355,7 @@ async fn test_lookup_asn() {
 }
 
 #[tokio::test]
+#[cfg(feature = "tokio")]
 async fn test_lookup_prefix() {
     use super::geoip2::City;
     let _ = env_logger::try_init();
@@ -365,7 +383,7 @@ async fn test_lookup_prefix() {
     assert_eq!(prefix_len, 26); // "2c0f:ff00::/26"
 }
 
-fn check_metadata<T: AsyncRead + AsyncSeek + Unpin>(reader: &Reader<T>, ip_version: usize, record_size: usize) {
+fn check_metadata<T: ReadAt>(reader: &Reader<T>, ip_version: usize, record_size: usize) {
     let metadata = &reader.metadata;
 
     assert_eq!(metadata.binary_format_major_version, 2_u16);
@@ -392,10 +410,26 @@ fn check_metadata<T: AsyncRead + AsyncSeek + Unpin>(reader: &Reader<T>, ip_versi
         assert_eq!(metadata.node_count, 416)
     }
 
-    assert_eq!(metadata.record_size, record_size as u16)
+    assert_eq!(metadata.record_size, record_size as u16);
+
+    assert_eq!(metadata.description_for("en"), Some("Test Database"));
+    assert_eq!(metadata.description_for("de"), None);
+    assert_eq!(metadata.record_size().unwrap().bits(), record_size);
+    assert_eq!(
+        metadata.ip_version().unwrap(),
+        if ip_version == 4 { IpVersion::V4 } else { IpVersion::V6 }
+    );
+    assert_eq!(
+        metadata.build_time(),
+        UNIX_EPOCH + Duration::from_secs(metadata.build_epoch)
+    );
+    assert_eq!(
+        metadata.search_tree_size(),
+        metadata.node_count as usize * record_size / 4
+    );
 }
 
-async fn check_ip<T: AsyncRead + AsyncSeek + Unpin>(reader: &mut Reader<T>, ip_version: usize) {
+async fn check_ip<T: ReadAt>(reader: &mut Reader<T>, ip_version: usize) {
     let subnets = match ip_version {
         6 => [
             "::1:ffff:ffff",
@@ -436,6 +470,8 @@ async fn check_ip<T: AsyncRead + AsyncSeek + Unpin>(reader: &mut Reader<T>, ip_v
         let ip: IpAddr = FromStr::from_str(address).unwrap();
         match reader.lookup::<IpType>(ip).await {
             Ok(v) => panic!("received an unexpected value: {:?}", v),
+            // IPv6 addresses can't be looked up in IPv4 databases
+            Err(MaxMindDBError::IpVersionMismatchError(_)) if ip_version == 4 && ip.is_ipv6() => {}
             Err(e) => assert_eq!(
                 e,
                 MaxMindDBError::AddressNotFoundError("Address not found in database".to_string())
@@ -443,3 +479,1592 @@ async fn check_ip<T: AsyncRead + AsyncSeek + Unpin>(reader: &mut Reader<T>, ip_v
         }
     }
 }
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_within() {
+    let _ = env_logger::try_init();
+
+    #[derive(Deserialize, Debug)]
+    struct IpType {
+        ip: String,
+    }
+
+    let expected = [
+        (
+            4,
+            vec!["1.1.1.1/32", "1.1.1.2/31", "1.1.1.4/30", "1.1.1.8/29", "1.1.1.16/28", "1.1.1.32/32"],
+        ),
+        (
+            6,
+            vec!["::1:ffff:ffff/128", "::2:0:0/122", "::2:0:40/124", "::2:0:50/125", "::2:0:58/127"],
+        ),
+    ];
+    for record_size in &[24_usize, 28, 32] {
+        for (ip_version, networks) in &expected {
+            let filename = format!(
+                "test-data/test-data/MaxMind-DB-test-ipv{}-{}.mmdb",
+                ip_version, record_size
+            );
+            let mut reader = Reader::open_readfile(&filename).await.unwrap();
+
+            let cidr: IpNetwork = if *ip_version == 4 { "0.0.0.0/0" } else { "::/0" }.parse().unwrap();
+            let mut iter = reader.within::<IpType>(cidr).await.unwrap();
+            let mut found = Vec::new();
+            while let Some(item) = iter.next().await.unwrap() {
+                assert_eq!(item.ip_net.ip().to_string(), item.info.ip);
+                found.push(item.ip_net.to_string());
+            }
+            assert_eq!(&found, networks);
+        }
+    }
+
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb")
+        .await
+        .unwrap();
+
+    // Only part of the tree
+    let cidr: IpNetwork = "1.1.1.0/29".parse().unwrap();
+    let mut iter = reader.within::<IpType>(cidr).await.unwrap();
+    let mut found = Vec::new();
+    while let Some(item) = iter.next().await.unwrap() {
+        found.push(item.ip_net.to_string());
+    }
+    assert_eq!(found, vec!["1.1.1.1/32", "1.1.1.2/31", "1.1.1.4/30"]);
+
+    // A cidr inside a single network yields the enclosing network
+    let cidr: IpNetwork = "1.1.1.17/32".parse().unwrap();
+    let mut iter = reader.within::<IpType>(cidr).await.unwrap();
+    let item = iter.next().await.unwrap().unwrap();
+    assert_eq!(item.ip_net.to_string(), "1.1.1.16/28");
+    assert!(iter.next().await.unwrap().is_none());
+
+    // Nothing in the database
+    let cidr: IpNetwork = "2.0.0.0/8".parse().unwrap();
+    let mut iter = reader.within::<IpType>(cidr).await.unwrap();
+    assert!(iter.next().await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_lookup_value() {
+    let _ = env_logger::try_init();
+
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-decoder.mmdb")
+        .await
+        .unwrap();
+    let ip: IpAddr = FromStr::from_str("1.1.1.0").unwrap();
+    let value: Value = reader.lookup(ip).await.unwrap();
+
+    let map = match value {
+        Value::Map(map) => map,
+        v => panic!("expected a map, got {:?}", v),
+    };
+    assert_eq!(
+        map["array"],
+        Value::Array(vec![Value::Uint32(1), Value::Uint32(2), Value::Uint32(3)])
+    );
+    assert_eq!(map["boolean"], Value::Boolean(true));
+    assert_eq!(map["bytes"], Value::Bytes(vec![0, 0, 0, 42]));
+    assert_eq!(map["double"], Value::Double(42.123_456));
+    assert_eq!(map["float"], Value::Float(1.1));
+    assert_eq!(map["int32"], Value::Int32(-268_435_456));
+    assert_eq!(map["uint16"], Value::Uint16(100));
+    assert_eq!(map["uint32"], Value::Uint32(268_435_456));
+    assert_eq!(map["uint64"], Value::Uint64(1_152_921_504_606_846_976));
+    assert_eq!(
+        map["uint128"],
+        Value::Uint128(1_329_227_995_784_915_872_903_807_060_280_344_576)
+    );
+    assert_eq!(
+        map["utf8_string"],
+        Value::String("unicode! \u{262f} - \u{266b}".to_owned())
+    );
+}
+
+// Writes the database built by `writer` to memory
+fn write_database(writer: &super::writer::Writer) -> Vec<u8> {
+    let mut buf = Vec::new();
+    writer.write_to(&mut buf).unwrap();
+    buf
+}
+
+// The record of network `i` of `write_large_database`
+#[cfg(any(feature = "http", feature = "object-store"))]
+fn large_record(i: usize) -> Value {
+    Value::String(format!("{:0>500}", i))
+}
+
+// Writes an IPv4 database of 2000 networks, 10.0.0.0/24 to 10.7.207.0/24,
+// with records large enough for the data section to span many blocks of a
+// small cache. The last network's record is at the end of the data section.
+#[cfg(any(feature = "http", feature = "object-store"))]
+fn write_large_database() -> Vec<u8> {
+    let mut writer = super::writer::Writer::new("Test", 4).unwrap();
+    for i in 0..2000 {
+        let network = format!("10.{}.{}.0/24", i / 256, i % 256);
+        writer.insert(network.parse().unwrap(), large_record(i)).unwrap();
+    }
+    write_database(&writer)
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_diff() {
+    use super::diff::{diff, DiffSummary};
+    use super::writer::Writer;
+    let _ = env_logger::try_init();
+
+    // Same networks and records, different record sizes
+    let mut old = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv6-24.mmdb")
+        .await
+        .unwrap();
+    let mut new = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv6-32.mmdb")
+        .await
+        .unwrap();
+    let mut differences = diff(&mut old, &mut new).await.unwrap();
+    assert!(differences.next().await.unwrap().is_none());
+
+    let mut ipv4 = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb")
+        .await
+        .unwrap();
+    assert!(diff(&mut ipv4, &mut new).await.is_err());
+
+    let mut old = Reader::open_readfile("test-data/test-data/GeoIP2-Country-Test.mmdb")
+        .await
+        .unwrap();
+    let mut new = Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb")
+        .await
+        .unwrap();
+    let mut found = Vec::new();
+    let mut summary = DiffSummary::default();
+    {
+        let mut differences = diff(&mut old, &mut new).await.unwrap();
+        while let Some(difference) = differences.next().await.unwrap() {
+            assert_ne!(difference.old, difference.new);
+            summary.record(&difference);
+            found.push(difference);
+        }
+    }
+    assert!(!found.is_empty());
+    assert_eq!(summary.added + summary.removed + summary.changed, found.len());
+    assert!(summary.fields.contains_key("city"));
+
+    // Every difference matches what lookups in the two databases return
+    for difference in found.iter().take(50) {
+        let ip = difference.ip_net.ip();
+        assert_eq!(old.lookup::<Value>(ip).await.ok(), difference.old);
+        assert_eq!(new.lookup::<Value>(ip).await.ok(), difference.new);
+    }
+
+    // Only the old database has the ::ffff:0:0/96 alias, the new one has its
+    // own record there
+    let record = |name: &str| Value::String(name.to_owned());
+    let mut writer = Writer::new("Test", 6).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), record("a")).unwrap();
+    let mut old = Reader::from_bytes(write_database(&writer)).await.unwrap();
+    writer.insert("::ffff:0:0/96".parse().unwrap(), record("b")).unwrap();
+    let mut new = Reader::from_bytes(write_database(&writer)).await.unwrap();
+    let mapped: IpNetwork = "::ffff:0:0/96".parse().unwrap();
+    let mut summary = DiffSummary::default();
+    let mut differences = diff(&mut old, &mut new).await.unwrap();
+    while let Some(difference) = differences.next().await.unwrap() {
+        assert!(mapped.contains(difference.ip_net.ip()));
+        summary.record(&difference);
+    }
+    assert_eq!(summary.changed, 1);
+    assert!(summary.added > 0);
+    assert_eq!(summary.non_map_records, 1);
+    assert!(summary.fields.is_empty());
+}
+
+#[tokio::test]
+#[cfg(feature = "export")]
+async fn test_export() {
+    use super::export::{write_csv, write_jsonl};
+    let _ = env_logger::try_init();
+
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb")
+        .await
+        .unwrap();
+
+    let mut csv = Vec::new();
+    let count = write_csv(&mut reader, None, &["ip", "missing"], &mut csv).await.unwrap();
+    assert_eq!(count, 6);
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("network,ip,missing"));
+    assert_eq!(lines.next(), Some("1.1.1.1/32,1.1.1.1,"));
+    assert_eq!(lines.count(), 5);
+
+    let mut jsonl = Vec::new();
+    let cidr: IpNetwork = "1.1.1.0/30".parse().unwrap();
+    let count = write_jsonl(&mut reader, Some(cidr), &mut jsonl).await.unwrap();
+    assert_eq!(count, 2);
+    let jsonl = String::from_utf8(jsonl).unwrap();
+    assert_eq!(
+        jsonl.lines().collect::<Vec<_>>(),
+        vec![
+            r#"{"network":"1.1.1.1/32","record":{"ip":"1.1.1.1"}}"#,
+            r#"{"network":"1.1.1.2/31","record":{"ip":"1.1.1.2"}}"#,
+        ]
+    );
+
+    // Nested paths, arrays and non-scalar values
+    let mut reader = Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb")
+        .await
+        .unwrap();
+    let mut csv = Vec::new();
+    let cidr: IpNetwork = "89.160.20.112/28".parse().unwrap();
+    write_csv(
+        &mut reader,
+        Some(cidr),
+        &["country.iso_code", "subdivisions.0.iso_code", "continent.names"],
+        &mut csv,
+    )
+    .await
+    .unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let row = csv.lines().nth(1).unwrap();
+    assert!(row.starts_with("89.160.20.112/28,SE,E,"), "{}", row);
+    assert!(row.contains(r#""{""#), "{}", row);
+}
+
+#[tokio::test]
+async fn test_writer() {
+    use super::writer::Writer;
+    use std::collections::BTreeMap;
+    let _ = env_logger::try_init();
+
+    let record = |name: &str| {
+        let mut map = BTreeMap::new();
+        map.insert("name".to_owned(), Value::String(name.to_owned()));
+        Value::Map(map)
+    };
+
+    for record_size in &[24_u16, 28, 32] {
+        let mut writer = Writer::new("Test", 6).unwrap()
+            .record_size(*record_size)
+            .languages(&["en"])
+            .description("en", "Test Database");
+        writer.insert("1.1.1.0/24".parse().unwrap(), record("a")).unwrap();
+        // More specific networks split the existing ones
+        writer.insert("1.1.1.128/25".parse().unwrap(), record("b")).unwrap();
+        writer.insert("2001:db8::/32".parse().unwrap(), record("c")).unwrap();
+        let mut reader = Reader::from_bytes(write_database(&writer)).await.unwrap();
+        assert_eq!(reader.metadata.record_size, *record_size);
+        assert_eq!(reader.metadata.database_type, "Test");
+        assert_eq!(reader.metadata.languages, vec!["en".to_owned()]);
+
+        let lookup = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            reader.lookup_prefix::<Value>(lookup("1.1.1.1")).await.unwrap(),
+            (record("a"), 25)
+        );
+        assert_eq!(
+            reader.lookup_prefix::<Value>(lookup("1.1.1.200")).await.unwrap(),
+            (record("b"), 25)
+        );
+        assert_eq!(reader.lookup::<Value>(lookup("2001:db8::1")).await.unwrap(), record("c"));
+        // IPv4 aliases
+        assert_eq!(reader.lookup::<Value>(lookup("::ffff:1.1.1.1")).await.unwrap(), record("a"));
+        assert_eq!(reader.lookup::<Value>(lookup("2002:101:101::")).await.unwrap(), record("a"));
+        assert!(reader.lookup::<Value>(lookup("1.1.2.1")).await.is_err());
+
+        let cidr: IpNetwork = "::/0".parse().unwrap();
+        let mut iter = reader.within::<Value>(cidr).await.unwrap();
+        let mut networks = Vec::new();
+        while let Some(item) = iter.next().await.unwrap() {
+            networks.push(item.ip_net.to_string());
+        }
+        assert_eq!(networks, vec!["1.1.1.0/25", "1.1.1.128/25", "2001:db8::/32"]);
+    }
+
+    let mut writer = Writer::new("Test", 4).unwrap();
+    assert!(writer.insert("2001:db8::/32".parse().unwrap(), record("a")).is_err());
+    assert_eq!(
+        Writer::new("Test", 5).err(),
+        Some(MaxMindDBError::InvalidDatabaseError("unsupported IP version: 5".to_owned()))
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "import")]
+async fn test_import() {
+    use super::import::{import_csv, import_jsonl, FieldType, Schema};
+    use super::writer::Writer;
+    let _ = env_logger::try_init();
+
+    let schema = Schema::new()
+        .field("autonomous_system_number", "asn", FieldType::Uint32)
+        .field("country.iso_code", "country", FieldType::String)
+        .field("is_anycast", "anycast", FieldType::Bool)
+        .field("score", "score", FieldType::Double);
+
+    let csv = "network,asn,country,anycast,score,ignored\n\
+               1.1.1.0/24,13335,AU,true,0.5,x\n\
+               2001:db8::/32,64496,,0,,y\n";
+    let mut writer = Writer::new("Test", 6).unwrap();
+    assert_eq!(import_csv(&mut writer, &schema, csv.as_bytes()).unwrap(), 2);
+    let mut reader = Reader::from_bytes(write_database(&writer)).await.unwrap();
+
+    let ip: IpAddr = "1.1.1.1".parse().unwrap();
+    let value: Value = reader.lookup(ip).await.unwrap();
+    assert_eq!(value.get_path("autonomous_system_number"), Some(&Value::Uint32(13335)));
+    assert_eq!(value.get_path("country.iso_code"), Some(&Value::String("AU".to_owned())));
+    assert_eq!(value.get_path("is_anycast"), Some(&Value::Boolean(true)));
+    assert_eq!(value.get_path("score"), Some(&Value::Double(0.5)));
+    let ip: IpAddr = "2001:db8::1".parse().unwrap();
+    let value: Value = reader.lookup(ip).await.unwrap();
+    assert_eq!(value.get_path("is_anycast"), Some(&Value::Boolean(false)));
+    assert_eq!(value.get_path("country"), None);
+
+    let jsonl = r#"{"network": "1.1.1.0/24", "asn": 13335, "country": {"iso_code": "AU"}}
+
+{"network": "1.0.0.0/24", "asn": "13335", "anycast": true, "score": null}
+"#;
+    let schema = Schema::new()
+        .field("autonomous_system_number", "asn", FieldType::Uint32)
+        .field("country.iso_code", "country.iso_code", FieldType::String)
+        .field("is_anycast", "anycast", FieldType::Bool)
+        .field("score", "score", FieldType::Double);
+    let mut writer = Writer::new("Test", 4).unwrap();
+    assert_eq!(import_jsonl(&mut writer, &schema, jsonl.as_bytes()).unwrap(), 2);
+    let mut reader = Reader::from_bytes(write_database(&writer)).await.unwrap();
+    let ip: IpAddr = "1.1.1.1".parse().unwrap();
+    let value: Value = reader.lookup(ip).await.unwrap();
+    assert_eq!(value.get_path("country.iso_code"), Some(&Value::String("AU".to_owned())));
+    let ip: IpAddr = "1.0.0.1".parse().unwrap();
+    let value: Value = reader.lookup(ip).await.unwrap();
+    assert_eq!(value.get_path("autonomous_system_number"), Some(&Value::Uint32(13335)));
+    assert_eq!(value.get_path("is_anycast"), Some(&Value::Boolean(true)));
+    assert_eq!(value.get_path("score"), None);
+
+    // Bad input
+    let schema = Schema::new().field("autonomous_system_number", "asn", FieldType::Uint32);
+    let mut writer = Writer::new("Test", 6).unwrap();
+    let csv = "network,asn\n1.1.1.0/24,big\n";
+    assert_eq!(
+        import_csv(&mut writer, &schema, csv.as_bytes()),
+        Err(MaxMindDBError::DecodingError(
+            "line 2: invalid Uint32 value `big` in column asn".to_owned()
+        ))
+    );
+    let csv = "network,asn\nnot-a-network,1\n";
+    assert!(import_csv(&mut writer, &schema, csv.as_bytes()).is_err());
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_metadata_serialize() {
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/MaxMind-DB-test-ipv6-24.mmdb";
+    let reader = Reader::open_readfile(filename).await.unwrap();
+
+    let json = serde_json::to_string(&reader.metadata).unwrap();
+    let metadata: super::Metadata = serde_json::from_str(&json).unwrap();
+    assert_eq!(metadata, reader.metadata);
+
+    let file_size = std::fs::metadata(filename).unwrap().len() as usize;
+    assert!(reader.data_section_size() > 0);
+    assert!(reader.metadata.search_tree_size() + 16 + reader.data_section_size() < file_size);
+}
+
+#[tokio::test]
+async fn test_metadata_extra() {
+    use super::writer::Writer;
+    let _ = env_logger::try_init();
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct BuildInfo {
+        database_type: String,
+        revision: String,
+        generator: String,
+    }
+
+    let mut writer = Writer::new("Test", 4).unwrap()
+        .metadata("revision", Value::String("r42".to_owned()))
+        .metadata("generator", Value::String("test".to_owned()))
+        // Keys of the format can't be overridden
+        .metadata("database_type", Value::String("Other".to_owned()));
+    writer.insert("1.1.1.0/24".parse().unwrap(), Value::Uint32(1)).unwrap();
+    let mut reader = Reader::from_bytes(write_database(&writer)).await.unwrap();
+    assert_eq!(reader.metadata.database_type, "Test");
+    assert_eq!(reader.metadata.extra.len(), 2);
+    assert_eq!(
+        reader.metadata.extra.get("revision"),
+        Some(&Value::String("r42".to_owned()))
+    );
+
+    let info: BuildInfo = reader.metadata_as().await.unwrap();
+    assert_eq!(
+        info,
+        BuildInfo {
+            database_type: "Test".to_owned(),
+            revision: "r42".to_owned(),
+            generator: "test".to_owned(),
+        }
+    );
+    // Lookups still work after decoding the metadata again
+    assert_eq!(
+        reader.lookup::<Value>("1.1.1.1").await.unwrap(),
+        Value::Uint32(1)
+    );
+
+    let missing: Result<BTreeMap<String, u32>, _> = reader.metadata_as().await;
+    assert!(missing.is_err());
+}
+
+#[tokio::test]
+async fn test_invalid_metadata() {
+    use super::source::Source;
+    use super::writer::Writer;
+    let _ = env_logger::try_init();
+
+    let mut writer = Writer::new("Test", 4).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), Value::Uint32(1)).unwrap();
+    let buf = write_database(&writer);
+
+    // Replaces the small unsigned integer stored under `key` in the metadata
+    let patch = |key: &str, value: &[u8]| {
+        let mut buf = buf.clone();
+        let start = memchr::memmem::rfind(&buf, key.as_bytes()).unwrap() + key.len();
+        let end = start + 1 + (buf[start] & 0x1f) as usize;
+        buf.splice(start..end, value.iter().copied());
+        buf
+    };
+    let open = |buf: Vec<u8>| async { Reader::from_bytes(buf).await.err().unwrap() };
+
+    assert_eq!(
+        open(patch("binary_format_major_version", &[0xa1, 3])).await,
+        MaxMindDBError::InvalidDatabaseError("unsupported binary_format_major_version: 3".to_owned())
+    );
+    assert_eq!(
+        open(patch("record_size", &[0xa1, 20])).await,
+        MaxMindDBError::InvalidDatabaseError("unsupported record_size: 20".to_owned())
+    );
+    assert_eq!(
+        open(patch("ip_version", &[0xa1, 5])).await,
+        MaxMindDBError::InvalidDatabaseError("unsupported ip_version: 5".to_owned())
+    );
+    match open(patch("node_count", &[0xc2, 0xff, 0xff])).await {
+        MaxMindDBError::InvalidDatabaseError(msg) => {
+            assert!(msg.starts_with("search tree of 65535 nodes does not fit"), "{}", msg)
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    // Experimental versions of the format can still be opened
+    let mut reader = Reader::from_source_unchecked(Source::from_bytes(patch("binary_format_major_version", &[0xa1, 3])))
+        .await
+        .unwrap();
+    assert_eq!(reader.metadata.binary_format_major_version, 3);
+    assert_eq!(
+        reader.lookup::<Value>("1.1.1.1").await.unwrap(),
+        Value::Uint32(1)
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_reader_options() {
+    use super::geoip2::City;
+    use super::ReaderOptions;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let ips = ["89.160.20.112", "89.160.20.128", "2001:218::", "81.2.69.160"];
+
+    let mut reader = Reader::open_readfile(filename).await.unwrap();
+    let mut expected = Vec::new();
+    for ip in ips {
+        expected.push(reader.lookup::<City>(ip).await.unwrap());
+    }
+
+    let options = [
+        ReaderOptions::new().cache_bytes(8192),
+        ReaderOptions::new().preload_levels(8),
+        ReaderOptions::new().buffer_size(1),
+        ReaderOptions::new().verify(false),
+        ReaderOptions::new().load_search_tree(true),
+    ];
+    for options in options.iter() {
+        let mut reader = options.open(filename).await.unwrap();
+        assert_eq!(reader.metadata.database_type, "GeoIP2-City");
+        for (ip, expected) in ips.iter().zip(&expected) {
+            let city: City = reader.lookup(*ip).await.unwrap();
+            assert_eq!(format!("{:?}", city), format!("{:?}", expected));
+        }
+    }
+
+    for record_size in &[24, 28, 32] {
+        for ip_version in &[4, 6] {
+            let filename = format!(
+                "test-data/test-data/MaxMind-DB-test-ipv{}-{}.mmdb",
+                ip_version, record_size
+            );
+            let mut reader = ReaderOptions::new()
+                .cache_bytes(4096)
+                .preload_levels(200)
+                .open(&filename)
+                .await
+                .unwrap();
+            check_metadata(&reader, *ip_version, *record_size);
+            check_ip(&mut reader, *ip_version).await;
+
+            let mut reader = ReaderOptions::new().load_search_tree(true).open(&filename).await.unwrap();
+            assert_eq!(
+                reader.search_tree.as_ref().map(|tree| tree.len()),
+                Some(reader.metadata.search_tree_size())
+            );
+            check_ip(&mut reader, *ip_version).await;
+        }
+    }
+}
+
+#[test]
+fn test_blocking_reader() {
+    use super::blocking;
+    use super::geoip2::City;
+    use rayon::prelude::*;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let mut reader = blocking::Reader::open_readfile(filename).unwrap();
+    assert_eq!(reader.metadata().database_type, "GeoIP2-City");
+
+    let city: City = reader.lookup("89.160.20.112").unwrap();
+    assert_eq!(city.country.and_then(|c| c.iso_code), Some("SE".to_owned()));
+    let (_, prefix_len) = reader.lookup_prefix::<Value>("89.160.20.112").unwrap();
+    assert_eq!(prefix_len, 28);
+    assert_eq!(
+        reader.lookup::<Value>("10.0.0.1"),
+        Err(MaxMindDBError::AddressNotFoundError("Address not found in database".to_owned()))
+    );
+
+    let networks: Vec<String> = reader
+        .within::<Value>("89.160.20.0/24".parse().unwrap())
+        .unwrap()
+        .map(|item| item.unwrap().ip_net.to_string())
+        .collect();
+    assert_eq!(networks, vec!["89.160.20.112/28", "89.160.20.128/25"]);
+
+    // One reader per thread
+    let ips = ["89.160.20.112", "89.160.20.128", "2001:218::", "81.2.69.160"];
+    let found: Vec<bool> = ips
+        .par_iter()
+        .map_init(
+            || blocking::Reader::open_readfile(filename).unwrap(),
+            |reader, ip| reader.lookup::<City>(*ip).is_ok(),
+        )
+        .collect();
+    assert_eq!(found, vec![true; 4]);
+}
+
+#[test]
+#[cfg(feature = "futures-io")]
+fn test_futures_io() {
+    use super::{FuturesIo, Source};
+    let _ = env_logger::try_init();
+
+    let buf = std::fs::read("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb").unwrap();
+    let source = Source::from_read_at(FuturesIo(futures::io::Cursor::new(buf.clone())), buf.len());
+    // No tokio runtime involved
+    futures::executor::block_on(async {
+        let mut reader = Reader::from_source(source).await.unwrap();
+        check_metadata(&reader, 4, 24);
+        check_ip(&mut reader, 4).await;
+    });
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_tokio_io() {
+    use super::{Source, TokioIo};
+    let _ = env_logger::try_init();
+
+    let file = tokio::fs::File::open("test-data/test-data/MaxMind-DB-test-ipv6-28.mmdb").await.unwrap();
+    let size = file.metadata().await.unwrap().len() as usize;
+    let source = Source::from_read_at(TokioIo(tokio::io::BufReader::new(file)), size);
+    let mut reader = Reader::from_source(source).await.unwrap();
+    check_metadata(&reader, 6, 28);
+    check_ip(&mut reader, 6).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg(feature = "tokio")]
+async fn test_pread_reader() {
+    use super::geoip2::City;
+    use super::ReaderOptions;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let reader = ReaderOptions::new()
+        .preload_levels(8)
+        .open_pread(filename)
+        .await
+        .unwrap();
+
+    // Each task looks up through its own clone of the reader
+    let ips = ["89.160.20.112", "89.160.20.128", "2001:218::", "81.2.69.160"];
+    let tasks: Vec<_> = ips
+        .iter()
+        .map(|ip| {
+            let mut reader = reader.clone();
+            let ip: IpAddr = ip.parse().unwrap();
+            tokio::spawn(async move { reader.lookup::<City>(ip).await.map(|city| city.country.is_some()) })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), Ok(true));
+    }
+
+    let mut reader = Reader::open_pread("test-data/test-data/MaxMind-DB-test-ipv4-32.mmdb").await.unwrap();
+    check_metadata(&reader, 4, 32);
+    check_ip(&mut reader, 4).await;
+}
+
+#[test]
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn test_uring_reader() {
+    let _ = env_logger::try_init();
+
+    tokio_uring::start(async {
+        let mut reader = Reader::open_uring("test-data/test-data/MaxMind-DB-test-ipv6-24.mmdb").await.unwrap();
+        check_metadata(&reader, 6, 24);
+        check_ip(&mut reader, 6).await;
+    });
+}
+
+#[tokio::test]
+#[cfg(all(feature = "gzip", feature = "zstd"))]
+async fn test_compressed() {
+    use super::blocking;
+    use super::compressed::{decompress, Compression};
+    use std::fs::File;
+    use std::io::Write;
+    let _ = env_logger::try_init();
+
+    let original = std::fs::read("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let gz = dir.path().join("GeoIP2-City-Test.mmdb.gz");
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&gz).unwrap(), Default::default());
+    encoder.write_all(&original).unwrap();
+    encoder.finish().unwrap();
+
+    let zst = dir.path().join("GeoIP2-City-Test.mmdb.zst");
+    zstd::stream::copy_encode(&original[..], File::create(&zst).unwrap(), 0).unwrap();
+
+    let tar_gz = dir.path().join("GeoIP2-City-Test_20240102.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(File::create(&tar_gz).unwrap(), Default::default());
+    let mut archive = tar::Builder::new(encoder);
+    for (name, data) in [
+        ("GeoIP2-City-Test_20240102/LICENSE.txt", &b"license"[..]),
+        ("GeoIP2-City-Test_20240102/GeoIP2-City-Test.mmdb", &original[..]),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, name, data).unwrap();
+    }
+    archive.into_inner().unwrap().finish().unwrap();
+
+    assert_eq!(Compression::from_path(&gz), Some(Compression::Gzip));
+    assert_eq!(Compression::from_path(&zst), Some(Compression::Zstd));
+    assert_eq!(Compression::from_path(&tar_gz), Some(Compression::TarGz));
+    assert_eq!(Compression::from_path("GeoIP2-City-Test.mmdb"), None);
+
+    let ip: IpAddr = "89.160.20.112".parse().unwrap();
+    for path in [&gz, &zst, &tar_gz] {
+        let mut reader = Reader::open_compressed(path).await.unwrap();
+        assert_eq!(reader.metadata.database_type, "GeoIP2-City");
+        let (_, prefix_len) = reader.lookup_prefix::<Value>(ip).await.unwrap();
+        assert_eq!(prefix_len, 28);
+
+        let mut reader = blocking::Reader::open_compressed(path).unwrap();
+        assert!(reader.lookup::<Value>(ip).is_ok());
+    }
+
+    // An archive without a database
+    let mut empty = Vec::new();
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(&mut empty, Default::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(7);
+    header.set_cksum();
+    archive.append_data(&mut header, "LICENSE.txt", &b"license"[..]).unwrap();
+    archive.into_inner().unwrap().finish().unwrap();
+    assert_eq!(
+        decompress(&empty[..], Compression::TarGz),
+        Err(MaxMindDBError::InvalidDatabaseError("no .mmdb file in archive".to_owned()))
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "gzip")]
+async fn test_open_archive() {
+    use super::compressed::{open_archive, read_archive, ArchiveDate};
+    use std::fs::File;
+    let _ = env_logger::try_init();
+
+    let city = std::fs::read("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    let country = std::fs::read("test-data/test-data/GeoIP2-Country-Test.mmdb").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let write_archive = |path: &std::path::Path, entries: &[(&str, &[u8])]| {
+        let encoder =
+            flate2::write::GzEncoder::new(File::create(path).unwrap(), Default::default());
+        let mut archive = tar::Builder::new(encoder);
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append_data(&mut header, name, *data).unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap();
+    };
+
+    let path = dir.path().join("GeoIP2-Test_20240102.tar.gz");
+    write_archive(
+        &path,
+        &[
+            ("GeoIP2-Test_20240102/COPYRIGHT.txt", b"copyright"),
+            ("GeoIP2-Test_20240102/GeoIP2-Country.mmdb", &country),
+            ("GeoIP2-Test_20240102/GeoIP2-City.mmdb", &city),
+        ],
+    );
+
+    let date = ArchiveDate {
+        year: 2024,
+        month: 1,
+        day: 2,
+    };
+    let database = open_archive(&path, Some("GeoIP2-City")).unwrap();
+    assert_eq!(database.edition, "GeoIP2-City");
+    assert_eq!(database.date, Some(date));
+    let reader = database.into_reader().await.unwrap();
+    assert_eq!(reader.metadata.database_type, "GeoIP2-City");
+
+    let database = open_archive(&path, None).unwrap();
+    assert_eq!(database.edition, "GeoIP2-Country");
+    let reader = database.into_reader().await.unwrap();
+    assert_eq!(reader.metadata.database_type, "GeoIP2-Country");
+
+    assert_eq!(
+        open_archive(&path, Some("GeoIP2-ISP")).unwrap_err(),
+        MaxMindDBError::InvalidDatabaseError("no GeoIP2-ISP database in archive".to_owned())
+    );
+
+    // Without a dated directory the date comes from the archive's name
+    let path = dir.path().join("GeoIP2-City_20240305.tar.gz");
+    write_archive(&path, &[("GeoIP2-City.mmdb", &city)]);
+    assert_eq!(
+        read_archive(File::open(&path).unwrap(), None).unwrap().date,
+        None
+    );
+    let database = open_archive(&path, Some("GeoIP2-City")).unwrap();
+    assert_eq!(database.date, Some("20240305".parse().unwrap()));
+
+    // Editions are picked by database_type, whatever the file is called
+    let path = dir.path().join("Renamed_20240305.tar.gz");
+    write_archive(
+        &path,
+        &[
+            ("Renamed_20240305/GeoIP2-City.mmdb", &country),
+            ("Renamed_20240305/custom.mmdb", &city),
+        ],
+    );
+    let database = open_archive(&path, Some("GeoIP2-City")).unwrap();
+    assert_eq!(database.edition, "GeoIP2-City");
+    assert_eq!(database.bytes, city);
+    assert_eq!(open_archive(&path, None).unwrap().edition, "GeoIP2-Country");
+    assert!(open_archive(&path, Some("custom")).is_err());
+    write_archive(&path, &[("Renamed_20240305/broken.mmdb", b"not a database")]);
+    assert_eq!(
+        open_archive(&path, None).unwrap_err(),
+        MaxMindDBError::InvalidDatabaseError("no .mmdb file in archive".to_owned())
+    );
+
+    // Files that aren't databases are skipped
+    write_archive(
+        &path,
+        &[
+            ("Renamed_20240305/broken.mmdb", b"not a database"),
+            ("Renamed_20240305/GeoIP2-City.mmdb", &city),
+        ],
+    );
+    assert_eq!(open_archive(&path, None).unwrap().edition, "GeoIP2-City");
+    assert_eq!(open_archive(&path, Some("GeoIP2-City")).unwrap().bytes, city);
+
+    assert_eq!("20240102".parse::<ArchiveDate>().unwrap(), date);
+    assert_eq!(date.to_string(), "20240102");
+    assert!("20240102".parse::<ArchiveDate>().unwrap() < "20240305".parse().unwrap());
+    assert!("2024010".parse::<ArchiveDate>().is_err());
+    assert!("20241302".parse::<ArchiveDate>().is_err());
+}
+
+// Serves `database` like the GeoIP update service, answering with 304 when
+// the client's MD5 matches. The MD5 header is `md5` when set.
+#[cfg(feature = "updater")]
+async fn serve_updates(database: std::sync::Arc<std::sync::Mutex<(Vec<u8>, Option<String>)>>) -> String {
+    use md5::{Digest, Md5};
+    use std::io::Write;
+
+    serve_http(move |request| {
+        let path = request.split(' ').nth(1).unwrap();
+        let (database, md5_header) = database.lock().unwrap().clone();
+        let md5 = format!("{:x}", Md5::digest(&database));
+        if !request.contains("authorization: Basic NDI6c2VjcmV0\r\n") {
+            b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 13\r\n\r\nInvalid login".to_vec()
+        } else if !path.starts_with("/geoip/databases/GeoIP2-City/update?db_md5=") {
+            b"HTTP/1.1 404 Not Found\r\ncontent-length: 26\r\n\r\nDatabase edition not found".to_vec()
+        } else if path.ends_with(&md5) {
+            b"HTTP/1.1 304 Not Modified\r\n\r\n".to_vec()
+        } else {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(&database).unwrap();
+            let body = encoder.finish().unwrap();
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nlast-modified: Tue, 02 Jan 2024 03:04:05 GMT\r\nx-database-md5: {}\r\n\r\n",
+                body.len(),
+                md5_header.unwrap_or(md5)
+            )
+            .into_bytes();
+            response.extend_from_slice(&body);
+            response
+        }
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg(feature = "updater")]
+async fn test_updater() {
+    use super::updater::{ReloadableReader, Update, Updater};
+    use super::ReaderOptions;
+    use std::sync::{Arc, Mutex};
+    let _ = env_logger::try_init();
+
+    let city = std::fs::read("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    let country = std::fs::read("test-data/test-data/GeoIP2-Country-Test.mmdb").unwrap();
+    let database = Arc::new(Mutex::new((city.clone(), None)));
+    let host = serve_updates(database.clone()).await;
+    let updater = Updater::new(42, "secret").host(&host);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("GeoIP2-City.mmdb");
+
+    let update = updater.update("GeoIP2-City", &path).await.unwrap();
+    assert!(matches!(update, Update::Updated { .. }));
+    assert_eq!(std::fs::read(&path).unwrap(), city);
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1704164645));
+    assert_eq!(updater.update("GeoIP2-City", &path).await.unwrap(), Update::Unchanged);
+
+    let reader = ReloadableReader::open(&path, ReaderOptions::new()).await.unwrap();
+    let old = reader.reader();
+    assert_eq!(old.metadata.database_type, "GeoIP2-City");
+    assert_eq!(updater.update_reader("GeoIP2-City", &reader).await.unwrap(), Update::Unchanged);
+    // Windows doesn't replace the file while `old` holds it open
+    #[cfg(not(unix))]
+    drop(old);
+
+    database.lock().unwrap().0 = country.clone();
+    let update = updater.update_reader("GeoIP2-City", &reader).await.unwrap();
+    assert!(matches!(update, Update::Updated { .. }));
+    assert_eq!(reader.reader().metadata.database_type, "GeoIP2-Country");
+    // Readers taken before the update keep reading the old database
+    #[cfg(unix)]
+    {
+        let mut old = old;
+        let ip: IpAddr = "89.160.20.112".parse().unwrap();
+        let (_, prefix_len) = old.lookup_prefix::<Value>(ip).await.unwrap();
+        assert_eq!(prefix_len, 28);
+    }
+
+    // A corrupt download leaves the file alone
+    *database.lock().unwrap() = (city.clone(), Some("0123456789abcdef0123456789abcdef".to_owned()));
+    assert!(matches!(
+        updater.update("GeoIP2-City", &path).await,
+        Err(MaxMindDBError::InvalidDatabaseError(_))
+    ));
+    *database.lock().unwrap() = (b"not a database".to_vec(), None);
+    assert!(updater.update("GeoIP2-City", &path).await.is_err());
+    assert!(updater.update_reader("GeoIP2-City", &reader).await.is_err());
+    assert_eq!(reader.reader().metadata.database_type, "GeoIP2-Country");
+    assert_eq!(std::fs::read(&path).unwrap(), country);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    assert_eq!(
+        updater.update("GeoIP2-ISP", &path).await.unwrap_err(),
+        MaxMindDBError::IoError(
+            "update of GeoIP2-ISP failed with 404 Not Found: Database edition not found".to_owned()
+        )
+    );
+    let updater = Updater::new(42, "wrong").host(&host);
+    assert!(matches!(
+        updater.update("GeoIP2-City", &path).await,
+        Err(MaxMindDBError::IoError(_))
+    ));
+}
+
+// A local HTTP server answering each request with `respond(request)`,
+// where the request is the request line and headers.
+#[cfg(any(feature = "http", feature = "updater"))]
+async fn serve_http<F>(respond: F) -> String
+where
+    F: Fn(&str) -> Vec<u8> + Send + Sync + 'static,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let respond = std::sync::Arc::new(respond);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        return;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    if request.ends_with(b"\r\n\r\n") {
+                        let response = respond(std::str::from_utf8(&request).unwrap());
+                        stream.write_all(&response).await.unwrap();
+                        request.clear();
+                    }
+                }
+            });
+        }
+    });
+    host
+}
+
+// Serves `database` as a static file, counting the requests. Range
+// requests are ignored unless `ranges` is set.
+#[cfg(feature = "http")]
+async fn serve_file(database: Vec<u8>, ranges: bool) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let requests = std::sync::Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let host = serve_http(move |request| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let range = request
+            .lines()
+            .find_map(|line| line.strip_prefix("range: bytes="))
+            .filter(|_| ranges)
+            .map(|range| {
+                let (start, end) = range.split_once('-').unwrap();
+                let end: usize = end.parse().unwrap();
+                (start.parse::<usize>().unwrap(), (end + 1).min(database.len()))
+            });
+        let (status, body) = match range {
+            Some((start, _)) if start >= database.len() => ("416 Range Not Satisfiable", &[][..]),
+            Some((start, end)) => ("206 Partial Content", &database[start..end]),
+            None => ("200 OK", &database[..]),
+        };
+        let mut response = format!("HTTP/1.1 {}\r\ncontent-length: {}\r\n\r\n", status, body.len()).into_bytes();
+        if !request.starts_with("HEAD ") {
+            response.extend_from_slice(body);
+        }
+        response
+    })
+    .await;
+    (format!("{}/GeoIP2-City-Test.mmdb", host), requests)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg(feature = "http")]
+async fn test_http_reader() {
+    use super::{HttpFile, ReaderOptions};
+    use std::sync::atomic::Ordering;
+    let _ = env_logger::try_init();
+
+    let database = std::fs::read("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    let (url, requests) = serve_file(database.clone(), true).await;
+
+    let mut reader = Reader::open_http(&url).await.unwrap();
+    assert_eq!(reader.metadata.database_type, "GeoIP2-City");
+    let mut expected = Reader::from_bytes(database.clone()).await.unwrap();
+    let ip: IpAddr = "89.160.20.112".parse().unwrap();
+    let city = reader.lookup::<Value>(ip).await.unwrap();
+    assert_eq!(city, expected.lookup::<Value>(ip).await.unwrap());
+
+    // Lookups are served from the block cache
+    let before = requests.load(Ordering::SeqCst);
+    assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), city);
+    assert_eq!(requests.load(Ordering::SeqCst), before);
+
+    // The whole search tree is read with a single request
+    let (url, requests) = serve_file(database.clone(), true).await;
+    let options = ReaderOptions::new().cache_bytes(1 << 20).load_search_tree(true);
+    let mut reader = options.open_http(&url).await.unwrap();
+    assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), city);
+    assert!(requests.load(Ordering::SeqCst) <= 5);
+
+    let mut file = HttpFile::open(&url).await.unwrap();
+    let mut buf = [0; 4];
+    file.read_exact_at(0, &mut buf).await.unwrap();
+    assert_eq!(buf, database[..4]);
+    assert!(file.read_exact_at(database.len() as u64 - 2, &mut buf).await.is_err());
+
+    let (url, _) = serve_file(database, false).await;
+    assert_eq!(
+        Reader::open_http(&url).await.err(),
+        Some(MaxMindDBError::IoError(format!("{} doesn't support range requests", url)))
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "object-store")]
+async fn test_object_store_reader() {
+    use super::{ObjectStoreFile, ReaderOptions};
+    use object_store::{memory::InMemory, path::Path, ObjectStore};
+    use std::sync::Arc;
+    let _ = env_logger::try_init();
+
+    let database = std::fs::read("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+    let mut expected = Reader::from_bytes(database.clone()).await.unwrap();
+    let ip: IpAddr = "89.160.20.112".parse().unwrap();
+    let city = expected.lookup::<Value>(ip).await.unwrap();
+
+    let store = Arc::new(InMemory::new());
+    let path = Path::from("databases/GeoIP2-City.mmdb");
+    store.put(&path, database.clone().into()).await.unwrap();
+    let file = ObjectStoreFile::open(store.clone(), path).await.unwrap();
+    let options = ReaderOptions::new().cache_bytes(1 << 20);
+    let mut reader = options.open_object_store(file.clone()).await.unwrap();
+    assert_eq!(reader.metadata.database_type, "GeoIP2-City");
+    assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), city);
+
+    let mut buf = [0; 4];
+    assert!(file.read_at(database.len() as u64 - 2, &mut buf).await.is_err());
+    assert!(ObjectStoreFile::open(store, Path::from("missing.mmdb")).await.is_err());
+
+    let url = format!(
+        "file://{}",
+        std::fs::canonicalize("test-data/test-data/GeoIP2-City-Test.mmdb").unwrap().display()
+    );
+    let mut reader = Reader::open_object_store(&url).await.unwrap();
+    assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), city);
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_lookup_addr() {
+    use super::writer::Writer;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    let _ = env_logger::try_init();
+
+    let record = Value::String("a".to_owned());
+    let mut writer = Writer::new("Test", 6).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), record.clone()).unwrap();
+    writer.insert("2001:db8::/32".parse().unwrap(), record.clone()).unwrap();
+    let mut reader = Reader::from_bytes(write_database(&writer)).await.unwrap();
+
+    let expected = (record.clone(), 24);
+    let v4 = Ipv4Addr::new(1, 1, 1, 1);
+    let mapped = v4.to_ipv6_mapped();
+    assert_eq!(reader.lookup_prefix::<Value>(v4).await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>(IpAddr::V4(v4)).await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>("1.1.1.1").await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>(&"1.1.1.1".to_owned()).await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>([1, 1, 1, 1]).await.unwrap(), expected);
+    let socket: SocketAddr = "1.1.1.1:443".parse().unwrap();
+    assert_eq!(reader.lookup_prefix::<Value>(socket).await.unwrap(), expected);
+    // IPv4-mapped addresses are looked up in the IPv4 subtree, their prefix
+    // length counting the 96 bits of ::ffff:0:0/96
+    let expected = (record.clone(), 120);
+    assert_eq!(reader.lookup_prefix::<Value>(mapped).await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>(IpAddr::V6(mapped)).await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>("::ffff:1.1.1.1").await.unwrap(), expected);
+    assert_eq!(reader.lookup_prefix::<Value>(mapped.octets()).await.unwrap(), expected);
+    let socket: SocketAddr = "[::ffff:1.1.1.1]:443".parse().unwrap();
+    assert_eq!(reader.lookup_prefix::<Value>(socket).await.unwrap(), expected);
+    assert_eq!(reader.path_for(mapped).await.unwrap(), reader.path_for(v4).await.unwrap());
+
+    let v6: Ipv6Addr = "2001:db8::1".parse().unwrap();
+    assert_eq!(reader.lookup_prefix::<Value>(v6).await.unwrap(), (record.clone(), 32));
+    assert_eq!(reader.lookup_prefix::<Value>(v6.octets()).await.unwrap(), (record.clone(), 32));
+    assert_eq!(
+        reader.lookup::<Value>("1.1.1.x").await,
+        Err(MaxMindDBError::InvalidNetworkError("invalid address: 1.1.1.x".to_owned()))
+    );
+
+    // IPv4-mapped addresses also work in IPv4 databases
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb").await.unwrap();
+    let expected = reader.lookup_prefix::<Value>("1.1.1.1").await.unwrap();
+    assert_eq!(reader.lookup_prefix::<Value>("::ffff:1.1.1.1").await.unwrap(), expected);
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_ip_version_mismatch() {
+    let _ = env_logger::try_init();
+
+    for record_size in &[24, 28, 32] {
+        let filename = format!("test-data/test-data/MaxMind-DB-test-ipv4-{}.mmdb", record_size);
+        let mut reader = Reader::open_readfile(&filename).await.unwrap();
+
+        assert_eq!(
+            reader.lookup::<Value>("2001:db8::1").await,
+            Err(MaxMindDBError::IpVersionMismatchError(
+                "IPv6 address 2001:db8::1 in an IPv4-only database".to_owned()
+            ))
+        );
+        assert!(matches!(
+            reader.lookup_prefix::<Value>("::1.1.1.1").await,
+            Err(MaxMindDBError::IpVersionMismatchError(_))
+        ));
+        let cidr: IpNetwork = "2001:db8::/32".parse().unwrap();
+        assert!(matches!(
+            reader.within::<Value>(cidr).await.err(),
+            Some(MaxMindDBError::IpVersionMismatchError(_))
+        ));
+
+        // IPv4-mapped addresses are unwrapped
+        let (record, prefix_len) = reader.lookup_prefix::<Value>("::ffff:1.1.1.1").await.unwrap();
+        assert_eq!(record, reader.lookup::<Value>("1.1.1.1").await.unwrap());
+        assert_eq!(prefix_len, 32);
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_networks() {
+    use super::writer::Writer;
+    use super::{blocking, NetworksOptions};
+    let _ = env_logger::try_init();
+
+    let record = |name: &str| Value::String(name.to_owned());
+    let mut writer = Writer::new("Test", 6).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), record("a")).unwrap();
+    writer.insert("1.1.2.0/24".parse().unwrap(), Value::Map(BTreeMap::new())).unwrap();
+    writer.insert("2001:db8::/32".parse().unwrap(), record("b")).unwrap();
+    let buf = write_database(&writer);
+    let mut reader = Reader::from_bytes(buf.clone()).await.unwrap();
+
+    async fn networks(reader: &mut Reader<std::io::Cursor<Vec<u8>>>, options: NetworksOptions) -> Vec<String> {
+        let mut iter = reader.networks::<Value>(options).await.unwrap();
+        let mut networks = Vec::new();
+        while let Some(item) = iter.next().await.unwrap() {
+            let info = match item.info {
+                Some(Value::String(name)) => name,
+                Some(_) => "{}".to_owned(),
+                None => "-".to_owned(),
+            };
+            networks.push(format!("{} {}", item.ip_net, info));
+        }
+        networks
+    }
+
+    // Aliases are visited, empty records and networks without data skipped
+    assert_eq!(
+        networks(&mut reader, NetworksOptions::new()).await,
+        vec!["1.1.1.0/24 a", "::ffff:1.1.1.0/120 a", "2001:db8::/32 b", "2002:101:100::/40 a"]
+    );
+    let options = NetworksOptions::new().skip_aliased_networks(true);
+    assert_eq!(networks(&mut reader, options).await, vec!["1.1.1.0/24 a", "2001:db8::/32 b"]);
+    let options = options.include_empty_networks(true);
+    assert_eq!(
+        networks(&mut reader, options).await,
+        vec!["1.1.1.0/24 a", "1.1.2.0/24 {}", "2001:db8::/32 b"]
+    );
+
+    let options = options.include_networks_without_data(true);
+    let all = networks(&mut reader, options).await;
+    assert!(all.contains(&"1.1.0.0/24 -".to_owned()));
+    assert!(all.contains(&"1.1.3.0/24 -".to_owned()));
+    assert!(all.contains(&"128.0.0.0/1 -".to_owned()));
+    assert!(all.contains(&"8000::/1 -".to_owned()));
+    // Aliased networks stay skipped, including those without data
+    assert!(!all.iter().any(|network| network.starts_with("::ffff:") || network.starts_with("2002:")));
+
+    let mut reader = blocking::Reader::from_bytes(buf).unwrap();
+    let networks: Vec<String> = reader
+        .networks::<Value>(NetworksOptions::new().skip_aliased_networks(true))
+        .unwrap()
+        .map(|item| item.unwrap().ip_net.to_string())
+        .collect();
+    assert_eq!(networks, vec!["1.1.1.0/24", "2001:db8::/32"]);
+
+    // IPv4 databases have no aliases
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb").await.unwrap();
+    let mut iter = reader.networks::<Value>(NetworksOptions::new()).await.unwrap();
+    let item = iter.next().await.unwrap().unwrap();
+    assert_eq!(item.ip_net.to_string(), "1.1.1.1/32");
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_find_networks() {
+    use super::geoip2::{City, Isp};
+    use super::{blocking, NetworksOptions};
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let mut reader = Reader::open_readfile(filename).await.unwrap();
+    let in_sweden = |city: &City| city.country.as_ref().and_then(|country| country.iso_code.as_deref()) == Some("SE");
+
+    let mut decoded = 0;
+    let found = reader
+        .find_networks(|city: &City| {
+            decoded += 1;
+            in_sweden(city)
+        })
+        .await
+        .unwrap();
+    assert!(found.iter().any(|network| network.to_string() == "89.160.20.112/28"));
+
+    // The same networks as testing the record of every network
+    let options = NetworksOptions::new().skip_aliased_networks(true);
+    let mut iter = reader.networks::<City>(options).await.unwrap();
+    let mut expected = Vec::new();
+    let mut networks = 0;
+    while let Some(item) = iter.next().await.unwrap() {
+        networks += 1;
+        if item.info.as_ref().is_some_and(in_sweden) {
+            expected.push(item.ip_net);
+        }
+    }
+    assert_eq!(found, expected);
+    assert!(decoded < networks);
+    // Each distinct record is decoded once
+    assert_eq!(decoded, reader.stats().await.unwrap().unique_records);
+
+    let mut reader = blocking::Reader::open_readfile("test-data/test-data/GeoIP2-ISP-Test.mmdb").unwrap();
+    let found = reader
+        .find_networks(|isp: &Isp| isp.autonomous_system_number == Some(7018))
+        .unwrap();
+    assert!(!found.is_empty());
+    for network in found {
+        let isp: Isp = reader.lookup(network.network()).unwrap();
+        assert_eq!(isp.autonomous_system_number, Some(7018));
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_index_by() {
+    use super::blocking;
+    use super::geoip2::City;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let mut reader = Reader::open_readfile(filename).await.unwrap();
+    let index = reader.index_by::<String>("country.iso_code").await.unwrap();
+    assert!(index.contains_key("SE"));
+    assert!(index.get("XX").is_empty());
+    assert!(index.keys().zip(index.keys().skip(1)).all(|(a, b)| a < b));
+
+    let in_sweden = reader
+        .find_networks(|city: &City| {
+            city.country.as_ref().and_then(|country| country.iso_code.as_deref()) == Some("SE")
+        })
+        .await
+        .unwrap();
+    assert_eq!(index.get("SE"), in_sweden);
+    let total: usize = index.iter().map(|(_, networks)| networks.len()).sum();
+    assert!(total > in_sweden.len());
+
+    // Cached indexes are built on first use and shared by clones
+    let mut reader = Reader::from_bytes(std::fs::read(filename).unwrap()).await.unwrap();
+    let cached = reader.cached_index::<String>("country.iso_code").await.unwrap();
+    assert_eq!(*cached, index);
+    let mut clone = reader.clone();
+    let again = clone.cached_index::<String>("country.iso_code").await.unwrap();
+    assert!(std::sync::Arc::ptr_eq(&cached, &again));
+
+    // Numeric fields decode into any integer type they fit
+    let mut reader = blocking::Reader::open_readfile("test-data/test-data/GeoIP2-ISP-Test.mmdb").unwrap();
+    let index = reader.index_by::<u64>("autonomous_system_number").unwrap();
+    assert!(!index.get(&7018).is_empty());
+    for network in index.get(&7018) {
+        let value: Value = reader.lookup(network.network()).unwrap();
+        assert_eq!(value.get_path("autonomous_system_number"), Some(&Value::Uint32(7018)));
+    }
+    assert!(matches!(
+        reader.index_by::<bool>("autonomous_system_number"),
+        Err(MaxMindDBError::DecodingError(_))
+    ));
+    assert!(reader.index_by::<String>("no.such.field").unwrap().is_empty());
+    let cached = reader.cached_index::<u64>("autonomous_system_number").unwrap();
+    assert!(std::sync::Arc::ptr_eq(&cached, &reader.cached_index::<u64>("autonomous_system_number").unwrap()));
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_stats() {
+    use super::blocking;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let mut reader = Reader::open_readfile(filename).await.unwrap();
+    let stats = reader.stats().await.unwrap();
+    assert_eq!(stats.node_count, reader.metadata.node_count as usize);
+    assert_eq!(stats.nodes_per_depth.values().sum::<usize>(), stats.node_count);
+    assert_eq!(stats.nodes_per_depth.get(&0), Some(&1));
+
+    let mut networks = 0;
+    let mut iter = reader.within::<Value>("::/0".parse().unwrap()).await.unwrap();
+    while iter.next().await.unwrap().is_some() {
+        networks += 1;
+    }
+    assert_eq!(stats.networks, networks);
+    let per_prefix_len = stats.ipv4_networks_per_prefix_len.values().sum::<usize>()
+        + stats.ipv6_networks_per_prefix_len.values().sum::<usize>();
+    assert_eq!(per_prefix_len, stats.networks);
+    assert!(stats.ipv4_networks_per_prefix_len.keys().all(|&len| len <= 32));
+    assert!(stats.networks_without_data > 0);
+
+    assert!(stats.unique_records > 0 && stats.unique_records <= stats.networks);
+    assert!(stats.record_bytes <= stats.data_section_size);
+    assert!(stats.bytes_per_key.contains_key("country"));
+    assert!(stats.bytes_per_key.values().sum::<usize>() < stats.record_bytes);
+    assert!(stats.pointer_targets <= stats.pointers);
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["node_count"], stats.node_count);
+
+    let mut reader = blocking::Reader::open_readfile(filename).unwrap();
+    assert_eq!(reader.stats().unwrap(), stats);
+
+    // Records without maps have no keys
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb")
+        .await
+        .unwrap();
+    let stats = reader.stats().await.unwrap();
+    assert!(stats.ipv6_networks_per_prefix_len.is_empty());
+    assert!(stats.networks > 0);
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_tree_introspection() {
+    use super::blocking;
+    use super::Record;
+    let _ = env_logger::try_init();
+
+    let filename = "test-data/test-data/GeoIP2-City-Test.mmdb";
+    let mut reader = Reader::open_readfile(filename).await.unwrap();
+    let node_count = reader.metadata.node_count as usize;
+
+    // Following the path by hand ends at the record of the lookup
+    let ip = "89.160.20.128";
+    let path = reader.path_for(ip).await.unwrap();
+    let (_, prefix_len) = reader.lookup_prefix::<Value>(ip).await.unwrap();
+    assert_eq!(path.len(), prefix_len);
+    let bits = u32::from(ip.parse::<std::net::Ipv4Addr>().unwrap());
+    let mut end = None;
+    for (i, &node) in path.iter().enumerate() {
+        let (left, right) = reader.node(node).await.unwrap();
+        let record = if bits & (1 << (31 - i)) == 0 { left } else { right };
+        match path.get(i + 1) {
+            Some(&next) => assert_eq!(record, Record::Node(next)),
+            None => end = Some(record),
+        }
+    }
+    let offset = match end {
+        Some(Record::Data(offset)) => offset,
+        other => panic!("expected a data record, got {:?}", other),
+    };
+    assert!(offset < reader.data_section_size());
+
+    // IPv4 lookups start at the IPv4 subtree, IPv6 ones at the root
+    let ipv6_path = reader.path_for("::89.160.20.128").await.unwrap();
+    assert_eq!(ipv6_path[0], 0);
+    assert_eq!(ipv6_path[96..], path[..]);
+
+    assert!(reader.node(node_count).await.is_err());
+    let mut empty = false;
+    for node in 0..node_count {
+        let (left, right) = reader.node(node).await.unwrap();
+        empty |= left == Record::Empty || right == Record::Empty;
+    }
+    assert!(empty);
+
+    let mut reader = blocking::Reader::open_readfile(filename).unwrap();
+    assert_eq!(reader.path_for(ip).unwrap(), path);
+    let mut reader = Reader::open_readfile("test-data/test-data/MaxMind-DB-test-ipv4-24.mmdb")
+        .await
+        .unwrap();
+    assert!(matches!(
+        reader.path_for("2001::").await,
+        Err(MaxMindDBError::IpVersionMismatchError(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg(feature = "http")]
+async fn test_http_reader_deep_lookup() {
+    use super::ReaderOptions;
+    use std::sync::atomic::Ordering;
+
+    let database = write_large_database();
+    let (url, requests) = serve_file(database.clone(), true).await;
+
+    let options = ReaderOptions::new().cache_bytes(16 * 1024).load_search_tree(true);
+    let mut reader = options.open_http(&url).await.unwrap();
+    assert!(reader.data_section_size() > 32 * 16 * 1024);
+
+    // The last record is read from its own offset, not along with the data
+    // before it
+    let before = requests.load(Ordering::SeqCst);
+    let record = reader.lookup::<Value>("10.7.207.1").await.unwrap();
+    assert_eq!(record, large_record(1999));
+    assert!(requests.load(Ordering::SeqCst) - before <= 2);
+
+    let mut expected = Reader::from_bytes(database).await.unwrap();
+    let ip: IpAddr = "10.3.4.5".parse().unwrap();
+    assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), expected.lookup::<Value>(ip).await.unwrap());
+}
+
+#[tokio::test]
+#[cfg(feature = "object-store")]
+async fn test_object_store_deep_lookup() {
+    use super::{ObjectStoreFile, ReaderOptions};
+    use futures::stream::BoxStream;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use object_store::{
+        GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOptions,
+        PutOptions, PutPayload, PutResult,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Counts the ranged GETs made against an in-memory store
+    #[derive(Debug, Default)]
+    struct CountingStore {
+        inner: InMemory,
+        gets: AtomicUsize,
+    }
+
+    impl std::fmt::Display for CountingStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "CountingStore({})", self.inner)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectStore for CountingStore {
+        async fn put_opts(&self, location: &Path, payload: PutPayload, opts: PutOptions) -> object_store::Result<PutResult> {
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(&self, location: &Path, options: GetOptions) -> object_store::Result<GetResult> {
+            if options.range.is_some() {
+                self.gets.fetch_add(1, Ordering::SeqCst);
+            }
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    let store = Arc::new(CountingStore::default());
+    let path = Path::from("databases/Test.mmdb");
+    store.put(&path, write_large_database().into()).await.unwrap();
+
+    let file = ObjectStoreFile::open(store.clone(), path).await.unwrap();
+    let options = ReaderOptions::new().cache_bytes(16 * 1024).load_search_tree(true);
+    let mut reader = options.open_object_store(file).await.unwrap();
+    assert!(reader.data_section_size() > 32 * 16 * 1024);
+
+    for (ip, i) in [("10.7.207.1", 1999), ("10.4.0.1", 1024), ("10.0.0.1", 0)] {
+        let before = store.gets.load(Ordering::SeqCst);
+        assert_eq!(reader.lookup::<Value>(ip).await.unwrap(), large_record(i));
+        assert!(store.gets.load(Ordering::SeqCst) - before <= 2);
+    }
+}
+
+#[tokio::test]
+async fn test_networks_empty_pointer_record() {
+    use super::writer::Writer;
+    use super::NetworksOptions;
+    let _ = env_logger::try_init();
+
+    let mut writer = Writer::new("Test", 4).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), Value::Map(BTreeMap::new())).unwrap();
+    writer.insert("1.1.2.0/24".parse().unwrap(), Value::String("xx".to_owned())).unwrap();
+    writer.insert("1.1.3.0/24".parse().unwrap(), Value::String("yy".to_owned())).unwrap();
+    let mut buf = write_database(&writer);
+
+    // Turn the record of 1.1.2.0/24 into a pointer to the empty map at the
+    // start of the data section
+    let at = buf.windows(3).position(|bytes| bytes == b"\x42xx").unwrap();
+    buf[at..at + 2].copy_from_slice(&[0x20, 0x00]);
+    let mut reader = Reader::from_bytes(buf).await.unwrap();
+    assert_eq!(reader.lookup::<Value>("1.1.2.1").await.unwrap(), Value::Map(BTreeMap::new()));
+
+    let mut iter = reader.networks::<Value>(NetworksOptions::new()).await.unwrap();
+    let mut networks = Vec::new();
+    while let Some(item) = iter.next().await.unwrap() {
+        networks.push(item.ip_net.to_string());
+    }
+    assert_eq!(networks, vec!["1.1.3.0/24"]);
+
+    let mut iter = reader
+        .networks::<Value>(NetworksOptions::new().include_empty_networks(true))
+        .await
+        .unwrap();
+    let mut networks = Vec::new();
+    while let Some(item) = iter.next().await.unwrap() {
+        networks.push(item.ip_net.to_string());
+    }
+    assert_eq!(networks, vec!["1.1.1.0/24", "1.1.2.0/24", "1.1.3.0/24"]);
+}
+
+#[tokio::test]
+async fn test_pointer_into_data_section_separator() {
+    use super::writer::Writer;
+    let _ = env_logger::try_init();
+
+    let mut writer = Writer::new("Test", 4).unwrap();
+    writer.insert("1.1.1.0/24".parse().unwrap(), Value::String("a".to_owned())).unwrap();
+    let mut buf = write_database(&writer);
+    let node_count = Reader::from_bytes(buf.clone()).await.unwrap().metadata.node_count as usize;
+
+    // Point the left record of the root into the data section separator
+    let record = (node_count + 1).to_be_bytes();
+    buf[..3].copy_from_slice(&record[record.len() - 3..]);
+    let mut reader = Reader::from_bytes(buf).await.unwrap();
+    assert_eq!(
+        reader.lookup::<Value>("1.1.1.1").await,
+        Err(MaxMindDBError::InvalidDatabaseError("invalid node in search tree".to_owned()))
+    );
+}
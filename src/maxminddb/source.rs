@@ -1,27 +1,420 @@
-use std::io::SeekFrom;
-
-use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, AsyncReadExt};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io::{self, Cursor};
 
 use crate::MaxMindDBError;
 
-pub struct Source<S: AsyncRead + AsyncSeek + Unpin> {
+// Size of the blocks kept by the block cache
+const BLOCK_SIZE: usize = 4096;
+
+/// Random access reads of the bytes of a database.
+///
+/// Readers are generic over this trait rather than over the I/O traits of a
+/// particular runtime. It's implemented for in-memory buffers, for
+/// `tokio::fs::File` and, with the `tokio` and `futures-io` features, for the
+/// [`TokioIo`] and [`FuturesIo`] adapters of other streams.
+pub trait ReadAt {
+    /// Fills `buf` with the bytes starting at `offset`, failing if the
+    /// database ends first.
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>>;
+}
+
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        let bytes = self.get_ref().as_ref();
+        let result = usize::try_from(offset)
+            .ok()
+            .and_then(|start| bytes.get(start..start.checked_add(buf.len())?))
+            .map(|bytes| buf.copy_from_slice(bytes))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof));
+        std::future::ready(result)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ReadAt for tokio::fs::File {
+    async fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        self.seek(io::SeekFrom::Start(offset)).await?;
+        self.read_exact(buf).await?;
+        Ok(())
+    }
+}
+
+/// Reads a database from any tokio [`AsyncRead`](tokio::io::AsyncRead) +
+/// [`AsyncSeek`](tokio::io::AsyncSeek) stream.
+#[cfg(feature = "tokio")]
+pub struct TokioIo<T>(pub T);
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin> ReadAt for TokioIo<T> {
+    async fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        self.0.seek(io::SeekFrom::Start(offset)).await?;
+        self.0.read_exact(buf).await?;
+        Ok(())
+    }
+}
+
+/// Reads a database from any [`futures_io::AsyncRead`] +
+/// [`futures_io::AsyncSeek`] stream, e.g. a file of async-std or smol.
+#[cfg(feature = "futures-io")]
+pub struct FuturesIo<T>(pub T);
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncRead + futures_io::AsyncSeek + Unpin> ReadAt for FuturesIo<T> {
+    async fn read_exact_at(&mut self, offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        use std::future::poll_fn;
+        use std::pin::Pin;
+
+        let stream = &mut self.0;
+        poll_fn(|cx| Pin::new(&mut *stream).poll_seek(cx, io::SeekFrom::Start(offset))).await?;
+        while !buf.is_empty() {
+            let read = poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, buf)).await?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            buf = &mut buf[read..];
+        }
+        Ok(())
+    }
+}
+
+/// A file read with positional reads (`pread`) that don't move a shared
+/// cursor. Reads take `&self` and clones share the file descriptor, so many
+/// tasks can read the same file concurrently without a mutex. The reads run
+/// on tokio's blocking thread pool.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug)]
+pub struct PreadFile {
+    file: std::sync::Arc<std::fs::File>,
+}
+
+#[cfg(feature = "tokio")]
+impl PreadFile {
+    pub async fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<PreadFile> {
+        let path = path.as_ref().to_owned();
+        let file = tokio::task::spawn_blocking(move || std::fs::File::open(path))
+            .await
+            .map_err(io::Error::other)??;
+        Ok(PreadFile {
+            file: std::sync::Arc::new(file),
+        })
+    }
+
+    /// Fills `buf` with the bytes of the file starting at `offset`.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let file = self.file.clone();
+        let len = buf.len();
+        let bytes = tokio::task::spawn_blocking(move || {
+            let mut bytes = vec![0; len];
+            pread_exact(&file, offset, &mut bytes).map(|_| bytes)
+        })
+        .await
+        .map_err(io::Error::other)??;
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// A source reading the whole file.
+    pub fn into_source(self) -> Result<Source<PreadFile>, MaxMindDBError> {
+        let total_size = self.file.metadata()?.len() as usize;
+        Ok(Source::from_read_at(self, total_size))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ReadAt for PreadFile {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        self.read_at(offset, buf)
+    }
+}
+
+#[cfg(all(feature = "tokio", unix))]
+fn pread_exact(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(all(feature = "tokio", windows))]
+fn pread_exact(file: &std::fs::File, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let read = std::os::windows::fs::FileExt::seek_read(file, buf, offset)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf = &mut buf[read..];
+        offset += read as u64;
+    }
+    Ok(())
+}
+
+/// A file read through Linux's io_uring, see [`tokio_uring`]. Clones share
+/// the file, and the reads of concurrent lookups are submitted to the same
+/// ring.
+///
+/// The file must be opened, read and dropped within a tokio-uring runtime,
+/// e.g. in [`tokio_uring::start`].
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[derive(Clone)]
+pub struct UringFile {
+    file: std::rc::Rc<tokio_uring::fs::File>,
+    size: usize,
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl UringFile {
+    pub async fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<UringFile> {
+        let size = std::fs::metadata(path.as_ref())?.len() as usize;
+        let file = tokio_uring::fs::File::open(path).await?;
+        Ok(UringFile {
+            file: std::rc::Rc::new(file),
+            size,
+        })
+    }
+
+    /// Fills `buf` with the bytes of the file starting at `offset`.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let bytes = Vec::with_capacity(buf.len() - filled);
+            let (read, bytes) = self.file.read_at(bytes, offset + filled as u64).await;
+            if read? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            buf[filled..filled + bytes.len()].copy_from_slice(&bytes);
+            filled += bytes.len();
+        }
+        Ok(())
+    }
+
+    /// A source reading the whole file.
+    pub fn into_source(self) -> Source<UringFile> {
+        let size = self.size;
+        Source::from_read_at(self, size)
+    }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl ReadAt for UringFile {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        self.read_at(offset, buf)
+    }
+}
+
+/// A database served over HTTP, read with `Range` requests, e.g. from a
+/// static file server or an object store. Clones share the connection pool.
+///
+/// Every read is a request, so the source is best used with a block cache,
+/// see [`ReaderOptions::cache_bytes`](crate::ReaderOptions::cache_bytes),
+/// and possibly with the search tree loaded into memory.
+#[cfg(feature = "http")]
+#[derive(Clone, Debug)]
+pub struct HttpFile {
+    client: reqwest::Client,
+    url: String,
+    size: usize,
+}
+
+#[cfg(feature = "http")]
+impl HttpFile {
+    pub async fn open(url: &str) -> io::Result<HttpFile> {
+        HttpFile::open_with_client(reqwest::Client::new(), url).await
+    }
+
+    /// Opens the database at `url` with a client of custom settings, e.g.
+    /// timeouts or default headers for authentication.
+    pub async fn open_with_client(client: reqwest::Client, url: &str) -> io::Result<HttpFile> {
+        let response = client.head(url).send().await.map_err(io::Error::other)?;
+        let response = response.error_for_status().map_err(io::Error::other)?;
+        let size = response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| io::Error::other(format!("no Content-Length for {}", url)))?;
+        Ok(HttpFile {
+            client,
+            url: url.to_owned(),
+            size,
+        })
+    }
+
+    /// Fills `buf` with the bytes of the file starting at `offset`.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let range = format!("bytes={}-{}", offset, offset + buf.len() as u64 - 1);
+        let response = self
+            .client
+            .get(&self.url)
+            .header(reqwest::header::RANGE, range)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        match response.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT => {}
+            reqwest::StatusCode::RANGE_NOT_SATISFIABLE => return Err(io::ErrorKind::UnexpectedEof.into()),
+            status if status.is_success() => {
+                return Err(io::Error::other(format!("{} doesn't support range requests", self.url)))
+            }
+            status => return Err(io::Error::other(format!("{} for {}", status, self.url))),
+        }
+        let bytes = response.bytes().await.map_err(io::Error::other)?;
+        if bytes.len() != buf.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// A source reading the whole file.
+    pub fn into_source(self) -> Source<HttpFile> {
+        let size = self.size;
+        Source::from_read_at(self, size)
+    }
+}
+
+#[cfg(feature = "http")]
+impl ReadAt for HttpFile {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        self.read_at(offset, buf)
+    }
+}
+
+/// A database in an object store of the [`object_store`] crate, read with
+/// ranged GETs. Clones share the store.
+///
+/// Like HTTP sources, reads should go through a block cache, see
+/// [`ReaderOptions::cache_bytes`](crate::ReaderOptions::cache_bytes).
+#[cfg(feature = "object-store")]
+#[derive(Clone, Debug)]
+pub struct ObjectStoreFile {
+    store: std::sync::Arc<dyn object_store::ObjectStore>,
+    path: object_store::path::Path,
+    size: usize,
+}
+
+#[cfg(feature = "object-store")]
+impl ObjectStoreFile {
+    pub async fn open(
+        store: std::sync::Arc<dyn object_store::ObjectStore>,
+        path: object_store::path::Path,
+    ) -> io::Result<ObjectStoreFile> {
+        let meta = store.head(&path).await?;
+        Ok(ObjectStoreFile {
+            store,
+            path,
+            size: meta.size as usize,
+        })
+    }
+
+    /// Opens the object at a URL such as `s3://bucket/GeoIP2-City.mmdb`,
+    /// `gs://bucket/GeoIP2-City.mmdb` or `file:///var/lib/GeoIP2-City.mmdb`.
+    /// The store is configured with `options`, e.g. [`std::env::vars`], see
+    /// [`object_store::parse_url_opts`]. S3 and Google Cloud Storage need the
+    /// `s3` and `gcs` features.
+    pub async fn open_url<I, K, V>(url: &str, options: I) -> io::Result<ObjectStoreFile>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        let url = url::Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let (store, path) = object_store::parse_url_opts(&url, options)?;
+        ObjectStoreFile::open(store.into(), path).await
+    }
+
+    /// Fills `buf` with the bytes of the object starting at `offset`.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = offset + buf.len() as u64;
+        if end > self.size as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let bytes = self.store.get_range(&self.path, offset..end).await?;
+        if bytes.len() != buf.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// A source reading the whole object.
+    pub fn into_source(self) -> Source<ObjectStoreFile> {
+        let size = self.size;
+        Source::from_read_at(self, size)
+    }
+}
+
+#[cfg(feature = "object-store")]
+impl ReadAt for ObjectStoreFile {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> impl Future<Output = io::Result<()>> {
+        self.read_at(offset, buf)
+    }
+}
+
+pub struct Source<S: ReadAt> {
     buffer: Vec<u8>,
     stream: S,
+    position: u64,
+    cache: Option<BlockCache>,
     pub total_size: usize,
 }
 
+#[cfg(feature = "tokio")]
 impl Source<tokio::fs::File> {
     pub async fn new(path: &str) -> Result<Source<tokio::fs::File>, MaxMindDBError> {
         let file = tokio::fs::File::open(path).await?;
-        Ok(Self {
+        let total_size = file.metadata().await?.len() as usize;
+        Ok(Source::from_read_at(file, total_size))
+    }
+}
+
+impl<T: AsRef<[u8]>> Source<Cursor<T>> {
+    pub fn from_bytes(buf: T) -> Source<Cursor<T>> {
+        let total_size = buf.as_ref().len();
+        Source::from_read_at(Cursor::new(buf), total_size)
+    }
+}
+
+/// Clones read from a clone of the stream with their own buffer and an
+/// empty cache of the same size.
+impl<S: ReadAt + Clone> Clone for Source<S> {
+    fn clone(&self) -> Source<S> {
+        Source {
             buffer: Vec::with_capacity(1024),
-            total_size: file.metadata().await?.len() as usize,
-            stream: file,
-        })
+            stream: self.stream.clone(),
+            position: 0,
+            cache: self.cache.as_ref().map(|cache| BlockCache::new(cache.capacity)),
+            total_size: self.total_size,
+        }
     }
 }
 
-impl<S: AsyncSeek + AsyncRead + Unpin> Source<S> {
+impl<S: ReadAt> Source<S> {
+    /// Reads a database of `total_size` bytes from `stream`.
+    pub fn from_read_at(stream: S, total_size: usize) -> Source<S> {
+        Self {
+            buffer: Vec::with_capacity(1024),
+            stream,
+            position: 0,
+            cache: None,
+            total_size,
+        }
+    }
+
+    /// Keeps up to `cache_bytes` of recently read blocks of the file in
+    /// memory, `0` disables the cache.
+    pub fn set_cache_bytes(&mut self, cache_bytes: usize) {
+        let capacity = cache_bytes / BLOCK_SIZE;
+        self.cache = if capacity == 0 { None } else { Some(BlockCache::new(capacity)) };
+    }
+
     /// based on sizes required should adjust the buffer, to keep it as small as possible,
     /// yet not relocate too often. For the experiment will always adjust to so far biggest size
     fn adjust_buffer(&mut self, size: usize) {
@@ -31,25 +424,123 @@ impl<S: AsyncSeek + AsyncRead + Unpin> Source<S> {
     }
 
     pub async fn position(&mut self) -> Result<u64, MaxMindDBError> {
-        Ok(self.stream.stream_position().await?)
+        Ok(self.position)
     }
 
     pub async fn move_cursor(&mut self, start: u64) -> Result<u64, MaxMindDBError> {
-        Ok(self.stream.seek(SeekFrom::Start(start)).await?)
+        self.position = start;
+        Ok(start)
     }
 
     pub async fn read(&mut self, size: usize) -> Result<&[u8], MaxMindDBError> {
-        self.adjust_buffer(size);
-        self.stream.read_exact(&mut self.buffer[..size]).await?;
-        Ok(&self.buffer[..size])
+        let start = self.position;
+        self.position += size as u64;
+        self.read_at(start, size).await
     }
 
     pub async fn read_at(&mut self, start: u64, size: usize) -> Result<&[u8], MaxMindDBError> {
-        self.move_cursor(start).await?;
-        Ok(self.read(size).await?)
+        self.adjust_buffer(size);
+        let cache = match &mut self.cache {
+            Some(cache) => cache,
+            None => {
+                self.stream.read_exact_at(start, &mut self.buffer[..size]).await?;
+                return Ok(&self.buffer[..size]);
+            }
+        };
+
+        let mut filled = 0;
+        while filled < size {
+            let position = start + filled as u64;
+            let index = position / BLOCK_SIZE as u64;
+            let offset = (position % BLOCK_SIZE as u64) as usize;
+            if !cache.contains(index) {
+                // The uncached blocks the read continues into are read at once
+                let last = (start + size as u64 - 1) / BLOCK_SIZE as u64;
+                let mut end = index + 1;
+                while end <= last && end - index < cache.capacity as u64 && !cache.contains(end) {
+                    end += 1;
+                }
+                let blocks = read_blocks(&mut self.stream, index, end, self.total_size).await?;
+                if blocks.is_empty() {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                for (i, block) in blocks.into_iter().enumerate() {
+                    cache.insert(index + i as u64, block);
+                }
+            }
+            let block = cache.get(index);
+            let len = (size - filled).min(block.len().saturating_sub(offset));
+            if len == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.buffer[filled..filled + len].copy_from_slice(&block[offset..offset + len]);
+            filled += len;
+        }
+        Ok(&self.buffer[..size])
     }
 
     pub async fn read_one(&mut self, start: u64) -> Result<u8, MaxMindDBError> {
-       Ok(self.read_at(start, 1).await?[0]) 
+       Ok(self.read_at(start, 1).await?[0])
     }
-}
\ No newline at end of file
+}
+
+// Reads the blocks from `first` up to `end`, the last block of the database
+// may be short.
+async fn read_blocks<S: ReadAt>(
+    stream: &mut S,
+    first: u64,
+    end: u64,
+    total_size: usize,
+) -> Result<Vec<Vec<u8>>, MaxMindDBError> {
+    let start = first as usize * BLOCK_SIZE;
+    let len = total_size.saturating_sub(start).min((end - first) as usize * BLOCK_SIZE);
+    let mut buf = vec![0; len];
+    stream.read_exact_at(start as u64, &mut buf).await?;
+    Ok(buf.chunks(BLOCK_SIZE).map(|block| block.to_vec()).collect())
+}
+
+/// The least recently used blocks are evicted once `capacity` blocks are
+/// cached.
+struct BlockCache {
+    capacity: usize,
+    tick: u64,
+    // Block index to last use and contents
+    blocks: HashMap<u64, (u64, Vec<u8>)>,
+    // Last use to block index
+    lru: BTreeMap<u64, u64>,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> BlockCache {
+        BlockCache {
+            capacity,
+            tick: 0,
+            blocks: HashMap::new(),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    fn contains(&self, index: u64) -> bool {
+        self.blocks.contains_key(&index)
+    }
+
+    fn insert(&mut self, index: u64, block: Vec<u8>) {
+        if self.blocks.len() >= self.capacity {
+            if let Some((_, evicted)) = self.lru.pop_first() {
+                self.blocks.remove(&evicted);
+            }
+        }
+        self.tick += 1;
+        self.lru.insert(self.tick, index);
+        self.blocks.insert(index, (self.tick, block));
+    }
+
+    fn get(&mut self, index: u64) -> &[u8] {
+        self.tick += 1;
+        let (used, block) = self.blocks.get_mut(&index).expect("block is cached");
+        self.lru.remove(used);
+        self.lru.insert(self.tick, index);
+        *used = self.tick;
+        block
+    }
+}
@@ -0,0 +1,140 @@
+//! A summary of what a database holds, for sizing caches, spotting
+//! duplicated data or comparing builds of a database.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut reader = maxminddb::Reader::open_readfile("test-data/test-data/GeoIP2-City-Test.mmdb").await.unwrap();
+//!
+//! let stats = reader.stats().await.unwrap();
+//! println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+
+use ipnetwork::IpNetwork;
+use serde::Serialize;
+
+use super::decoder::RecordLayout;
+use super::{MaxMindDBError, NetworksOptions, ReadAt, Reader, Value};
+
+/// The report of [`Reader::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// The number of nodes of the search tree
+    pub node_count: usize,
+    /// The number of nodes at each depth of the tree, the root being at
+    /// depth 0. Nodes reachable through several paths, such as the IPv4
+    /// subtree of IPv6 databases, are counted once at their shallowest depth.
+    pub nodes_per_depth: BTreeMap<usize, usize>,
+    /// The size of the search tree in bytes
+    pub search_tree_size: usize,
+    /// The number of networks with a record
+    pub networks: usize,
+    /// The number of networks the tree marks as having no data
+    pub networks_without_data: usize,
+    /// The number of IPv4 networks with a record per prefix length
+    pub ipv4_networks_per_prefix_len: BTreeMap<u8, usize>,
+    /// The number of IPv6 networks with a record per prefix length
+    pub ipv6_networks_per_prefix_len: BTreeMap<u8, usize>,
+    /// The size of the data section in bytes
+    pub data_section_size: usize,
+    /// The number of distinct records the networks point to
+    pub unique_records: usize,
+    /// The size of the distinct records in bytes, not counting the data
+    /// their pointers point to
+    pub record_bytes: usize,
+    /// The bytes taken by each top-level key of the distinct records, key
+    /// and value included
+    pub bytes_per_key: BTreeMap<String, usize>,
+    /// The number of pointers in the distinct records
+    pub pointers: usize,
+    /// The number of distinct values those pointers point to
+    pub pointer_targets: usize,
+}
+
+impl<S: ReadAt> Reader<S> {
+    /// Walks the search tree and the records of the data section and reports
+    /// their shape, see [`Stats`]. The IPv4 aliases of IPv6 databases are
+    /// skipped, so each network is counted once.
+    ///
+    /// Every node is read and every distinct record is scanned once, which
+    /// takes a while for large databases.
+    pub async fn stats(&mut self) -> Result<Stats, MaxMindDBError> {
+        let node_count = self.metadata.node_count as usize;
+        let mut stats = Stats {
+            node_count,
+            search_tree_size: self.metadata.search_tree_size(),
+            data_section_size: self.data_section_size(),
+            ..Stats::default()
+        };
+
+        let options = NetworksOptions::new()
+            .skip_aliased_networks(true)
+            .include_empty_networks(true)
+            .include_networks_without_data(true);
+        let mut iter = self.networks::<Value>(options).await?;
+        let mut records = Vec::new();
+        let mut seen = HashSet::new();
+        while let Some((ip_net, pointer)) = iter.inner.next_network().await? {
+            let pointer = match pointer {
+                Some(pointer) => pointer,
+                None => {
+                    stats.networks_without_data += 1;
+                    continue;
+                }
+            };
+            stats.networks += 1;
+            let per_prefix_len = match ip_net {
+                IpNetwork::V4(_) => &mut stats.ipv4_networks_per_prefix_len,
+                IpNetwork::V6(_) => &mut stats.ipv6_networks_per_prefix_len,
+            };
+            *per_prefix_len.entry(ip_net.prefix()).or_default() += 1;
+            if seen.insert(pointer) {
+                records.push(pointer);
+            }
+        }
+        stats.unique_records = records.len();
+
+        // Breadth first, so shared subtrees are counted at their shallowest
+        // depth
+        let mut visited = vec![false; node_count];
+        let mut level = vec![0];
+        let mut depth = 0;
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for node in level {
+                if node >= node_count || visited[node] {
+                    continue;
+                }
+                visited[node] = true;
+                *stats.nodes_per_depth.entry(depth).or_default() += 1;
+                next.push(self.read_node(node, 0).await?);
+                next.push(self.read_node(node, 1).await?);
+            }
+            level = next;
+            depth += 1;
+        }
+
+        let mut targets = HashSet::new();
+        for pointer in records {
+            let layout = self.record_layout(pointer).await?;
+            stats.record_bytes += layout.size;
+            for (key, size) in layout.keys {
+                *stats.bytes_per_key.entry(key).or_default() += size;
+            }
+            stats.pointers += layout.pointers.len();
+            targets.extend(layout.pointers);
+        }
+        stats.pointer_targets = targets.len();
+        Ok(stats)
+    }
+
+    async fn record_layout(&mut self, pointer: usize) -> Result<RecordLayout, MaxMindDBError> {
+        let rec = self.resolve_data_pointer(pointer)?;
+        self.decode_at(rec, |decoder| decoder.record_layout())
+            .await?
+            .ok_or_else(|| MaxMindDBError::InvalidDatabaseError("unexpected end of data decoding a record".to_owned()))
+    }
+}
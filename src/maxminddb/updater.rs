@@ -0,0 +1,296 @@
+//! Keeping databases up to date from MaxMind's update service.
+//!
+//! [`Updater`] downloads an edition with the GeoIP update protocol, the one
+//! `geoipupdate` speaks, using an account ID and license key. Databases are
+//! only downloaded when the copy on disk differs from the latest one, are
+//! verified before being used and replace the file atomically, so readers
+//! never see a partially written database. A [`ReloadableReader`] picks up
+//! the new database without interrupting lookups in flight:
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! use maxminddb::updater::{ReloadableReader, Updater};
+//! use maxminddb::ReaderOptions;
+//!
+//! let updater = Updater::new(42, "license key");
+//! let path = "/var/lib/GeoIP/GeoLite2-City.mmdb";
+//! updater.update("GeoLite2-City", path).await.unwrap();
+//!
+//! let reader = ReloadableReader::open(path, ReaderOptions::new()).await.unwrap();
+//! // Later, e.g. once a day
+//! updater.update_reader("GeoLite2-City", &reader).await.unwrap();
+//! let city: maxminddb::geoip2::City = reader.reader().lookup("89.160.20.128").await.unwrap();
+//! # }
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::SystemTime;
+
+use md5::{Digest, Md5};
+
+use super::compressed::{decompress, Compression};
+use super::{MaxMindDBError, PreadFile, Reader, ReaderOptions, Source};
+
+/// The update service of MaxMind.
+pub const DEFAULT_HOST: &str = "https://updates.maxmind.com";
+
+// What `geoipupdate` sends when there is no database yet
+const MISSING_MD5: &str = "00000000000000000000000000000000";
+
+/// The result of [`Updater::update`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Update {
+    /// The database on disk is the latest one.
+    Unchanged,
+    /// A new database was written, `md5` is its checksum.
+    Updated { md5: String },
+}
+
+/// A client of the GeoIP update service.
+#[derive(Clone, Debug)]
+pub struct Updater {
+    client: reqwest::Client,
+    host: String,
+    account_id: u32,
+    license_key: String,
+}
+
+impl Updater {
+    pub fn new(account_id: u32, license_key: &str) -> Updater {
+        Updater {
+            client: reqwest::Client::new(),
+            host: DEFAULT_HOST.to_owned(),
+            account_id,
+            license_key: license_key.to_owned(),
+        }
+    }
+
+    /// Sets the URL of the update server, e.g. a local mirror. Defaults to
+    /// [`DEFAULT_HOST`].
+    pub fn host(mut self, host: &str) -> Updater {
+        self.host = host.trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Downloads the `edition` database to `path` unless the file is already
+    /// the latest one.
+    ///
+    /// The MD5 of the file and its modification time are sent with the
+    /// request, so the server only answers with a database when it changed.
+    /// A new database is checked against the MD5 sent by the server and
+    /// opened before it replaces the file, and the file takes the server's
+    /// modification time.
+    pub async fn update<P: AsRef<Path>>(&self, edition: &str, path: P) -> Result<Update, MaxMindDBError> {
+        let path = path.as_ref().to_owned();
+        let download = match self.download(edition, &path).await? {
+            Some(download) => download,
+            None => return Ok(Update::Unchanged),
+        };
+        let tmp = write_temp(&path, download.buf, download.modified).await?;
+        if let Err(e) = fs::rename(&tmp, &path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(Update::Updated { md5: download.md5 })
+    }
+
+    /// Updates the database of `reader`, see [`Updater::update`], and
+    /// reloads it when it changed.
+    ///
+    /// The new database is opened and handed out by `reader` before it
+    /// replaces the file, as Windows doesn't allow replacing a file that is
+    /// open. There, readers taken with [`ReloadableReader::reader`] before
+    /// the update keep the old file open, and the update fails until they
+    /// are dropped. The file and `reader` are left as they were when the
+    /// update fails.
+    pub async fn update_reader(&self, edition: &str, reader: &ReloadableReader) -> Result<Update, MaxMindDBError> {
+        let download = match self.download(edition, reader.path()).await? {
+            Some(download) => download,
+            None => return Ok(Update::Unchanged),
+        };
+        let tmp = write_temp(reader.path(), download.buf, download.modified).await?;
+        let new = match reader.options.open_pread(&tmp).await {
+            Ok(new) => new,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+        // Dropping the old reader closes the file unless readers taken
+        // before still use it
+        drop(reader.replace(new));
+        if let Err(e) = fs::rename(&tmp, reader.path()) {
+            // The old file is still in place
+            let reloaded = reader.reload().await;
+            let _ = fs::remove_file(&tmp);
+            reloaded?;
+            return Err(e.into());
+        }
+        Ok(Update::Updated { md5: download.md5 })
+    }
+
+    // Downloads the `edition` database unless `path` is already the latest
+    // one, in which case `None` is returned. The database is checked
+    // against the MD5 sent by the server and opened.
+    async fn download(&self, edition: &str, path: &Path) -> Result<Option<Download>, MaxMindDBError> {
+        let (md5, modified) = match tokio::fs::read(path).await {
+            Ok(buf) => {
+                let modified = tokio::fs::metadata(path).await?.modified().ok();
+                (md5_hex(&buf), modified)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (MISSING_MD5.to_owned(), None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let url = format!("{}/geoip/databases/{}/update", self.host, edition);
+        let mut request = self
+            .client
+            .get(url)
+            .query(&[("db_md5", &md5)])
+            .basic_auth(self.account_id, Some(&self.license_key));
+        if let Some(modified) = modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(modified));
+        }
+        let response = request.send().await.map_err(http_error)?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(MaxMindDBError::IoError(format!(
+                "update of {} failed with {}: {}",
+                edition,
+                status,
+                body.trim()
+            )));
+        }
+        let expected_md5 = response
+            .headers()
+            .get("X-Database-MD5")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_ascii_lowercase())
+            .ok_or_else(|| MaxMindDBError::IoError("no X-Database-MD5 in update response".to_owned()))?;
+        let last_modified = response
+            .headers()
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        let body = response.bytes().await.map_err(http_error)?;
+
+        let buf = tokio::task::spawn_blocking(move || decompress(&body[..], Compression::Gzip))
+            .await
+            .map_err(io::Error::other)??;
+        let md5 = md5_hex(&buf);
+        if md5 != expected_md5 {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "MD5 of the downloaded {} is {}, expected {}",
+                edition, md5, expected_md5
+            )));
+        }
+        Reader::from_source(Source::from_bytes(&buf[..])).await?;
+        Ok(Some(Download {
+            buf,
+            md5,
+            modified: last_modified,
+        }))
+    }
+}
+
+// A verified database downloaded by `Updater::download`
+struct Download {
+    buf: Vec<u8>,
+    md5: String,
+    modified: Option<SystemTime>,
+}
+
+/// A reader whose database can be swapped for a new version of the file.
+///
+/// Clones share the database. Readers handed out by
+/// [`ReloadableReader::reader`] keep reading the database they were taken
+/// from, which stays readable after the file is replaced. On Windows they
+/// keep the file from being replaced, see [`Updater::update_reader`].
+#[derive(Clone)]
+pub struct ReloadableReader {
+    path: PathBuf,
+    options: ReaderOptions,
+    current: Arc<RwLock<Reader<PreadFile>>>,
+}
+
+impl ReloadableReader {
+    pub async fn open<P: AsRef<Path>>(path: P, options: ReaderOptions) -> Result<ReloadableReader, MaxMindDBError> {
+        let path = path.as_ref().to_owned();
+        let reader = options.open_pread(&path).await?;
+        Ok(ReloadableReader {
+            path,
+            options,
+            current: Arc::new(RwLock::new(reader)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A reader of the database as last loaded.
+    pub fn reader(&self) -> Reader<PreadFile> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Opens the file again and uses it for the readers handed out from now
+    /// on. The current database is kept if the file can't be opened.
+    pub async fn reload(&self) -> Result<(), MaxMindDBError> {
+        let reader = self.options.open_pread(&self.path).await?;
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = reader;
+        Ok(())
+    }
+
+    // Hands out `reader` from now on and returns the one it replaces
+    fn replace(&self, reader: Reader<PreadFile>) -> Reader<PreadFile> {
+        std::mem::replace(&mut *self.current.write().unwrap_or_else(PoisonError::into_inner), reader)
+    }
+}
+
+fn md5_hex(buf: &[u8]) -> String {
+    format!("{:x}", Md5::digest(buf))
+}
+
+fn http_error(err: reqwest::Error) -> MaxMindDBError {
+    MaxMindDBError::IoError(err.to_string())
+}
+
+// Writes the database to a temporary file next to `path`, which is then
+// renamed over the old one, so the file is always either the old or the new
+// database. Returns the path of the temporary file.
+async fn write_temp(path: &Path, buf: Vec<u8>, modified: Option<SystemTime>) -> Result<PathBuf, MaxMindDBError> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "database path has no file name"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let tmp = tokio::task::spawn_blocking(move || {
+        let result = (|| {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&buf)?;
+            if let Some(modified) = modified {
+                file.set_modified(modified)?;
+            }
+            file.sync_all()
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result.map(|()| tmp)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    Ok(tmp)
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, Deserialize, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::Serialize;
+
+use super::MaxMindDBError;
+
+/// A dynamically typed MaxMind DB value.
+///
+/// Each variant corresponds to one of the data types of the MaxMind DB
+/// format, so records of unknown shape can be decoded without losing type
+/// information. Serializing a `Value` produces the plain inner value, e.g. a
+/// `Value::Map` becomes a JSON object.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint16(u16),
+    Uint32(u32),
+    Map(BTreeMap<String, Value>),
+    Int32(i32),
+    Uint64(u64),
+    Uint128(u128),
+    Array(Vec<Value>),
+    Boolean(bool),
+    Float(f32),
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a MaxMind DB value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i32<E: de::Error>(self, v: i32) -> Result<Value, E> {
+        Ok(Value::Int32(v))
+    }
+
+    fn visit_u16<E: de::Error>(self, v: u16) -> Result<Value, E> {
+        Ok(Value::Uint16(v))
+    }
+
+    fn visit_u32<E: de::Error>(self, v: u32) -> Result<Value, E> {
+        Ok(Value::Uint32(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Uint64(v))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::Uint128(v))
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Double(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut array = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(v) = seq.next_element()? {
+            array.push(v);
+        }
+        Ok(Value::Array(array))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = BTreeMap::new();
+        while let Some((k, v)) = map.next_entry()? {
+            values.insert(k, v);
+        }
+        Ok(Value::Map(values))
+    }
+}
+
+impl Value {
+    /// Looks up a nested value by a dot separated path such as
+    /// `country.iso_code`. Array elements are addressed by their index, e.g.
+    /// `subdivisions.0.iso_code`.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, key| match value {
+            Value::Map(map) => map.get(key),
+            Value::Array(array) => key.parse::<usize>().ok().and_then(|i| array.get(i)),
+            _ => None,
+        })
+    }
+}
+
+/// Decodes a `Value` into another type, e.g. a field of a record found with
+/// [`Value::get_path`].
+impl<'de> Deserializer<'de> for Value {
+    type Error = MaxMindDBError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, MaxMindDBError>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::String(v) => visitor.visit_string(v),
+            Value::Double(v) => visitor.visit_f64(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::Uint16(v) => visitor.visit_u16(v),
+            Value::Uint32(v) => visitor.visit_u32(v),
+            Value::Map(v) => visitor.visit_map(MapDeserializer::new(v.into_iter())),
+            Value::Int32(v) => visitor.visit_i32(v),
+            Value::Uint64(v) => visitor.visit_u64(v),
+            Value::Uint128(v) => visitor.visit_u128(v),
+            Value::Array(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            Value::Boolean(v) => visitor.visit_bool(v),
+            Value::Float(v) => visitor.visit_f32(v),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, MaxMindDBError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, MaxMindDBError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
@@ -0,0 +1,407 @@
+//! Building new MaxMind DB files.
+//!
+//! ```
+//! use maxminddb::writer::Writer;
+//! use maxminddb::Value;
+//! use std::collections::BTreeMap;
+//!
+//! let mut writer = Writer::new("My-ASN-DB", 6).unwrap();
+//! let mut record = BTreeMap::new();
+//! record.insert("autonomous_system_number".to_owned(), Value::Uint32(13335));
+//! writer.insert("1.1.1.0/24".parse().unwrap(), Value::Map(record)).unwrap();
+//!
+//! let mut buf = Vec::new();
+//! writer.write_to(&mut buf).unwrap();
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ipnetwork::IpNetwork;
+
+use super::{MaxMindDBError, Value};
+
+const DATA_SECTION_SEPARATOR: [u8; 16] = [0; 16];
+const METADATA_START_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Record {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+/// Builds the search tree and data section of a new database in memory.
+///
+/// Networks are inserted with [`Writer::insert`]. When networks overlap,
+/// the addresses they share get the record of the network inserted last.
+/// IPv4 networks inserted into an IPv6 database are stored in the `::/96`
+/// subtree, which is also reachable through the usual `::ffff:0:0/96` and
+/// `2002::/16` aliases.
+pub struct Writer {
+    database_type: String,
+    ip_version: u16,
+    record_size: Option<u16>,
+    build_epoch: Option<u64>,
+    languages: Vec<String>,
+    description: BTreeMap<String, String>,
+    extra_metadata: BTreeMap<String, Value>,
+    // Node 0 is the root of the search tree
+    nodes: Vec<[Record; 2]>,
+    values: Vec<Value>,
+}
+
+impl Writer {
+    /// Creates an empty database of `database_type`, `ip_version` must be 4
+    /// or 6.
+    pub fn new(database_type: &str, ip_version: u16) -> Result<Writer, MaxMindDBError> {
+        if ip_version != 4 && ip_version != 6 {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "unsupported IP version: {}",
+                ip_version
+            )));
+        }
+        Ok(Writer {
+            database_type: database_type.to_owned(),
+            ip_version,
+            record_size: None,
+            build_epoch: None,
+            languages: Vec::new(),
+            description: BTreeMap::new(),
+            extra_metadata: BTreeMap::new(),
+            nodes: vec![[Record::Empty; 2]],
+            values: Vec::new(),
+        })
+    }
+
+    /// Sets the record size of the search tree, 24, 28 or 32 bits. By default
+    /// the smallest size able to address the whole database is used.
+    pub fn record_size(mut self, record_size: u16) -> Writer {
+        self.record_size = Some(record_size);
+        self
+    }
+
+    /// Sets the build time stored in the metadata, defaults to the time the
+    /// database is written.
+    pub fn build_epoch(mut self, build_epoch: u64) -> Writer {
+        self.build_epoch = Some(build_epoch);
+        self
+    }
+
+    pub fn languages(mut self, languages: &[&str]) -> Writer {
+        self.languages = languages.iter().map(|l| l.to_string()).collect();
+        self
+    }
+
+    pub fn description(mut self, language: &str, description: &str) -> Writer {
+        self.description.insert(language.to_owned(), description.to_owned());
+        self
+    }
+
+    /// Adds a custom `key` to the metadata, e.g. the revision of the source
+    /// data. Keys of the format itself, such as `node_count`, take precedence.
+    pub fn metadata(mut self, key: &str, value: Value) -> Writer {
+        self.extra_metadata.insert(key.to_owned(), value);
+        self
+    }
+
+    /// Associates `value` with every address of `network`.
+    pub fn insert(&mut self, network: IpNetwork, value: Value) -> Result<(), MaxMindDBError> {
+        let (bytes, prefix_len) = match (network.ip(), self.ip_version) {
+            (IpAddr::V4(ip), 4) => (ip.octets().to_vec(), network.prefix() as usize),
+            (IpAddr::V4(ip), _) => (ip.to_ipv6_compatible().octets().to_vec(), network.prefix() as usize + 96),
+            (IpAddr::V6(ip), 6) => (ip.octets().to_vec(), network.prefix() as usize),
+            (IpAddr::V6(_), _) => {
+                return Err(MaxMindDBError::InvalidNetworkError(format!(
+                    "cannot insert {} into an IPv4 database",
+                    network
+                )))
+            }
+        };
+        if prefix_len == 0 {
+            return Err(MaxMindDBError::InvalidNetworkError(format!(
+                "cannot insert {}, it covers the whole database",
+                network
+            )));
+        }
+
+        self.values.push(value);
+        let data = Record::Data(self.values.len() - 1);
+
+        let mut node = 0;
+        for i in 0..prefix_len - 1 {
+            let bit = bit_at(&bytes, i);
+            node = match self.nodes[node][bit] {
+                Record::Node(next) => next,
+                record => {
+                    // Split an empty or data record, both halves keep what
+                    // the record had so far
+                    self.nodes.push([record; 2]);
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node][bit] = Record::Node(next);
+                    next
+                }
+            };
+        }
+        self.nodes[node][bit_at(&bytes, prefix_len - 1)] = data;
+        Ok(())
+    }
+
+    /// Serializes the database in the MaxMind DB format.
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<(), MaxMindDBError> {
+        let mut nodes = self.nodes.clone();
+        if self.ip_version == 6 {
+            add_ipv4_aliases(&mut nodes);
+        }
+
+        // Number the nodes reachable from the root, depth first
+        let mut numbers = vec![None; nodes.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            if numbers[node].is_some() {
+                continue;
+            }
+            numbers[node] = Some(order.len());
+            order.push(node);
+            for record in nodes[node].iter().rev() {
+                if let Record::Node(child) = record {
+                    stack.push(*child);
+                }
+            }
+        }
+        let node_count = order.len();
+
+        let mut data = Vec::new();
+        let mut offsets: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut data_offsets = vec![0; self.values.len()];
+        for node in &order {
+            for record in &nodes[*node] {
+                if let Record::Data(index) = *record {
+                    let mut encoded = Vec::new();
+                    encode(&self.values[index], &mut encoded)?;
+                    data_offsets[index] = *offsets.entry(encoded).or_insert_with_key(|encoded| {
+                        data.extend_from_slice(encoded);
+                        data.len() - encoded.len()
+                    });
+                }
+            }
+        }
+
+        let max_value = node_count + DATA_SECTION_SEPARATOR.len() + data.len();
+        let record_size = match self.record_size {
+            Some(size) => size,
+            None if max_value < 1 << 24 => 24,
+            None if max_value < 1 << 28 => 28,
+            None => 32,
+        };
+        match record_size {
+            24 | 28 | 32 if (max_value as u64) < 1 << record_size => {}
+            24 | 28 | 32 => {
+                return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                    "database is too large for a record size of {}",
+                    record_size
+                )))
+            }
+            s => {
+                return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                    "unknown record size: {:?}",
+                    s
+                )))
+            }
+        }
+
+        let to_value = |record: Record| match record {
+            Record::Empty => node_count,
+            Record::Node(node) => numbers[node].expect("reachable node"),
+            Record::Data(index) => node_count + DATA_SECTION_SEPARATOR.len() + data_offsets[index],
+        };
+        let mut tree = Vec::with_capacity(node_count * record_size as usize / 4);
+        for node in &order {
+            let [left, right] = nodes[*node].map(to_value);
+            match record_size {
+                24 => {
+                    tree.extend_from_slice(&(left as u32).to_be_bytes()[1..]);
+                    tree.extend_from_slice(&(right as u32).to_be_bytes()[1..]);
+                }
+                28 => {
+                    tree.extend_from_slice(&(left as u32).to_be_bytes()[1..]);
+                    tree.push((((left >> 24) as u8) << 4) | (right >> 24) as u8);
+                    tree.extend_from_slice(&(right as u32).to_be_bytes()[1..]);
+                }
+                _ => {
+                    tree.extend_from_slice(&(left as u32).to_be_bytes());
+                    tree.extend_from_slice(&(right as u32).to_be_bytes());
+                }
+            }
+        }
+
+        let build_epoch = match self.build_epoch {
+            Some(epoch) => epoch,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        let mut metadata = self.extra_metadata.clone();
+        metadata.insert("binary_format_major_version".to_owned(), Value::Uint16(2));
+        metadata.insert("binary_format_minor_version".to_owned(), Value::Uint16(0));
+        metadata.insert("build_epoch".to_owned(), Value::Uint64(build_epoch));
+        metadata.insert("database_type".to_owned(), Value::String(self.database_type.clone()));
+        metadata.insert(
+            "description".to_owned(),
+            Value::Map(
+                self.description
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                    .collect(),
+            ),
+        );
+        metadata.insert("ip_version".to_owned(), Value::Uint16(self.ip_version));
+        metadata.insert(
+            "languages".to_owned(),
+            Value::Array(self.languages.iter().cloned().map(Value::String).collect()),
+        );
+        metadata.insert("node_count".to_owned(), Value::Uint32(node_count as u32));
+        metadata.insert("record_size".to_owned(), Value::Uint16(record_size));
+        let mut encoded_metadata = Vec::new();
+        encode(&Value::Map(metadata), &mut encoded_metadata)?;
+
+        out.write_all(&tree)?;
+        out.write_all(&DATA_SECTION_SEPARATOR)?;
+        out.write_all(&data)?;
+        out.write_all(METADATA_START_MARKER)?;
+        out.write_all(&encoded_metadata)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// Points the IPv4 aliases of an IPv6 database, ::ffff:0:0/96 and 2002::/16,
+// to its ::/96 subtree. Aliases are only added where nothing was inserted.
+fn add_ipv4_aliases(nodes: &mut Vec<[Record; 2]>) {
+    let ipv4_root = match follow(nodes, &[0; 16], 96) {
+        Some(Record::Node(node)) => node,
+        _ => return,
+    };
+
+    for (bytes, prefix_len) in [
+        ([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0], 96),
+        ([0x20, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 16),
+    ] {
+        if follow(nodes, &bytes, prefix_len) != Some(Record::Empty) {
+            continue;
+        }
+        let mut node = 0;
+        for i in 0..prefix_len - 1 {
+            let bit = bit_at(&bytes, i);
+            node = match nodes[node][bit] {
+                Record::Node(next) => next,
+                _ => {
+                    nodes.push([Record::Empty; 2]);
+                    nodes[node][bit] = Record::Node(nodes.len() - 1);
+                    nodes.len() - 1
+                }
+            };
+        }
+        nodes[node][bit_at(&bytes, prefix_len - 1)] = Record::Node(ipv4_root);
+    }
+}
+
+// Returns the record reached after walking `prefix_len` bits of `bytes`,
+// or `None` if a data record is reached before.
+fn follow(nodes: &[[Record; 2]], bytes: &[u8], prefix_len: usize) -> Option<Record> {
+    let mut record = Record::Node(0);
+    for i in 0..prefix_len {
+        match record {
+            Record::Node(node) => record = nodes[node][bit_at(bytes, i)],
+            Record::Empty => return Some(Record::Empty),
+            Record::Data(_) => return None,
+        }
+    }
+    Some(record)
+}
+
+fn bit_at(bytes: &[u8], i: usize) -> usize {
+    (1 & (bytes[i >> 3] >> (7 - (i % 8)))) as usize
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) -> Result<(), MaxMindDBError> {
+    match value {
+        Value::String(s) => {
+            encode_control(2, s.len(), out)?;
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Double(v) => {
+            encode_control(3, 8, out)?;
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        Value::Bytes(b) => {
+            encode_control(4, b.len(), out)?;
+            out.extend_from_slice(b);
+        }
+        Value::Uint16(v) => encode_uint(5, &v.to_be_bytes(), out)?,
+        Value::Uint32(v) => encode_uint(6, &v.to_be_bytes(), out)?,
+        Value::Map(map) => {
+            encode_control(7, map.len(), out)?;
+            for (k, v) in map {
+                encode(&Value::String(k.clone()), out)?;
+                encode(v, out)?;
+            }
+        }
+        // Negative numbers need all 4 bytes, positive ones are trimmed
+        // like unsigned integers
+        Value::Int32(v) if *v < 0 => {
+            encode_control(8, 4, out)?;
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        Value::Int32(v) => encode_uint(8, &v.to_be_bytes(), out)?,
+        Value::Uint64(v) => encode_uint(9, &v.to_be_bytes(), out)?,
+        Value::Uint128(v) => encode_uint(10, &v.to_be_bytes(), out)?,
+        Value::Array(array) => {
+            encode_control(11, array.len(), out)?;
+            for v in array {
+                encode(v, out)?;
+            }
+        }
+        Value::Boolean(v) => encode_control(14, *v as usize, out)?,
+        Value::Float(v) => {
+            encode_control(15, 4, out)?;
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn encode_uint(type_num: u8, bytes: &[u8], out: &mut Vec<u8>) -> Result<(), MaxMindDBError> {
+    let leading_zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    encode_control(type_num, bytes.len() - leading_zeros, out)?;
+    out.extend_from_slice(&bytes[leading_zeros..]);
+    Ok(())
+}
+
+fn encode_control(type_num: u8, size: usize, out: &mut Vec<u8>) -> Result<(), MaxMindDBError> {
+    let (size_bits, size_bytes): (u8, Vec<u8>) = match size {
+        s if s < 29 => (s as u8, vec![]),
+        s if s < 285 => (29, vec![(s - 29) as u8]),
+        s if s < 65_821 => (30, ((s - 285) as u16).to_be_bytes().to_vec()),
+        s if s < 16_843_037 => (31, ((s - 65_821) as u32).to_be_bytes()[1..].to_vec()),
+        s => {
+            return Err(MaxMindDBError::InvalidDatabaseError(format!(
+                "value of size {} is too large",
+                s
+            )))
+        }
+    };
+    if type_num <= 7 {
+        out.push((type_num << 5) | size_bits);
+    } else {
+        // Extended type
+        out.push(size_bits);
+        out.push(type_num - 7);
+    }
+    out.extend_from_slice(&size_bytes);
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::process;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use ipnetwork::IpNetwork;
+use maxminddb::diff::{self, DiffSummary};
+use maxminddb::export;
+use maxminddb::import::{self, FieldType, Schema};
+use maxminddb::writer::Writer;
+use maxminddb::{Reader, Value};
+
+/// Inspect MaxMind DB files
+#[derive(Parser)]
+#[command(name = "mmdb", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Look up an IP address and print its record as JSON
+    Lookup { database: String, ip: IpAddr },
+    /// Print the database metadata
+    Metadata { database: String },
+    /// Print statistics about the search tree and the records as JSON
+    Stats { database: String },
+    /// Print every network of the database, or of CIDR, with its record as JSON lines
+    Dump {
+        database: String,
+        cidr: Option<IpNetwork>,
+    },
+    /// Export every network of the database, or of CIDR, with selected fields of its record
+    Export {
+        database: String,
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        /// Comma separated record paths to export as CSV columns, e.g. country.iso_code
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+        #[arg(long)]
+        cidr: Option<IpNetwork>,
+    },
+    /// Compile a CSV or JSON Lines table of networks into a new database
+    Import {
+        input: String,
+        output: String,
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        #[arg(long)]
+        database_type: String,
+        #[arg(long, default_value_t = 6)]
+        ip_version: u16,
+        /// Field to import as PATH[=COLUMN]:TYPE, TYPE is one of uint16, uint32, string, bool or double
+        #[arg(long = "field", required = true)]
+        fields: Vec<String>,
+    },
+    /// Print the networks whose records differ between two databases as JSON lines
+    Diff {
+        old: String,
+        new: String,
+        /// Only print the number of differences, per top-level field
+        #[arg(long)]
+        summary: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Jsonl,
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run(Cli::parse().command).await {
+        eprintln!("mmdb: {}", err);
+        process::exit(1);
+    }
+}
+
+async fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Lookup { database, ip } => {
+            let mut reader = Reader::open_readfile(&database).await?;
+            let record: Value = reader.lookup(ip).await?;
+            println!("{}", serde_json::to_string_pretty(&record)?);
+        }
+        Command::Metadata { database } => {
+            let reader = Reader::open_readfile(&database).await?;
+            println!("{}", serde_json::to_string_pretty(&reader.metadata)?);
+        }
+        Command::Stats { database } => {
+            let mut reader = Reader::open_readfile(&database).await?;
+            println!("{}", serde_json::to_string_pretty(&reader.stats().await?)?);
+        }
+        Command::Dump { database, cidr } => {
+            let mut reader = Reader::open_readfile(&database).await?;
+            export::write_jsonl(&mut reader, cidr, BufWriter::new(io::stdout().lock())).await?;
+        }
+        Command::Export {
+            database,
+            format,
+            columns,
+            cidr,
+        } => {
+            let mut reader = Reader::open_readfile(&database).await?;
+            let out = BufWriter::new(io::stdout().lock());
+            match format {
+                Format::Csv => {
+                    let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                    export::write_csv(&mut reader, cidr, &columns, out).await?;
+                }
+                Format::Jsonl => {
+                    export::write_jsonl(&mut reader, cidr, out).await?;
+                }
+            }
+        }
+        Command::Import {
+            input,
+            output,
+            format,
+            database_type,
+            ip_version,
+            fields,
+        } => {
+            let mut schema = Schema::new();
+            for field in &fields {
+                let (name, field_type) = field
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("invalid field {}, expected PATH[=COLUMN]:TYPE", field))?;
+                let (path, column) = name.split_once('=').unwrap_or((name, name));
+                schema = schema.field(path, column, field_type.parse::<FieldType>()?);
+            }
+
+            let mut writer = Writer::new(&database_type, ip_version)?;
+            let input = BufReader::new(File::open(input)?);
+            let count = match format {
+                Format::Csv => import::import_csv(&mut writer, &schema, input)?,
+                Format::Jsonl => import::import_jsonl(&mut writer, &schema, input)?,
+            };
+            writer.write_to(BufWriter::new(File::create(output)?))?;
+            eprintln!("imported {} networks", count);
+        }
+        Command::Diff { old, new, summary } => {
+            let mut old = Reader::open_readfile(&old).await?;
+            let mut new = Reader::open_readfile(&new).await?;
+
+            let mut out = BufWriter::new(io::stdout().lock());
+            let mut counts = DiffSummary::default();
+            let mut diff = diff::diff(&mut old, &mut new).await?;
+            while let Some(difference) = diff.next().await? {
+                if summary {
+                    counts.record(&difference);
+                    continue;
+                }
+                let line = serde_json::json!({
+                    "network": difference.ip_net.to_string(),
+                    "old": difference.old,
+                    "new": difference.new,
+                });
+                writeln!(out, "{}", line)?;
+            }
+            if summary {
+                writeln!(out, "{}", serde_json::to_string_pretty(&counts)?)?;
+            }
+            out.flush()?;
+        }
+    }
+    Ok(())
+}